@@ -3,19 +3,260 @@ use aviutl2::{
     generic::ObjectHandle,
 };
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct FindTarget {
     pub start: bool,
     pub keyframe: bool,
     pub end: bool,
     pub project_end: bool,
+    pub downbeat_only: bool,
+    pub start_direction: crate::grid::SnapDirection,
+    pub keyframe_direction: crate::grid::SnapDirection,
+    pub end_direction: crate::grid::SnapDirection,
+    pub start_tolerance: Tolerance,
+    pub keyframe_tolerance: Tolerance,
+    pub end_tolerance: Tolerance,
+    /// これより小さいズレ（フレーム）は無視する
+    pub min_offset_frames: u64,
+    pub start_move: StartMove,
+    /// 開始位置・終了位置を合わせるときに、中間点を新しい長さに合わせて伸縮する
+    pub stretch_keyframes: bool,
+    /// Someの場合は位置ではなく長さを見て、この音価の整数倍になっていないものを探す
+    pub length_note: Option<crate::grid::NoteValue>,
+    /// 1〜2フレームの隙間しかない隣のオブジェクトもつながっているとみなし、補正で詰める
+    pub join_gaps: bool,
+}
+
+/// 開始位置を合わせるときの動かし方
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StartMove {
+    /// 開始位置だけを動かす（長さが変わる）
+    #[default]
+    Section,
+    /// 中間点ごとオブジェクト全体を動かす（長さが変わらない）
+    Object,
+}
+
+impl FindTarget {
+    /// このタイミングを補正するときに、中間点を伸縮するかどうか
+    fn stretches_keyframes(&self, timing_type: &TimingType) -> bool {
+        // NOTE: 長さを合わせるときは終了位置だけを動かす
+        self.stretch_keyframes
+            && self.length_note.is_none()
+            && !matches!(timing_type, TimingType::Keyframe { .. })
+    }
+
+    /// このタイミングを補正するときに、オブジェクトごと動かすかどうか
+    fn moves_whole_object(&self, timing_type: &TimingType) -> bool {
+        self.start
+            && self.length_note.is_none()
+            && !self.stretch_keyframes
+            && self.start_move == StartMove::Object
+            // NOTE: 後ろにつながっているオブジェクトも一緒にずらす（plan_chain_shift）
+            && matches!(
+                timing_type,
+                TimingType::Start { .. } | TimingType::EndThenStart { .. }
+            )
+    }
+
+    /// つながっているとみなす隣のオブジェクトとの隙間の上限（フレーム）
+    fn max_join_gap_frames(&self) -> usize {
+        if self.join_gaps {
+            MAX_JOIN_GAP_FRAMES
+        } else {
+            0
+        }
+    }
+
+    /// タイミングの種類ごとの許容範囲をフレーム数で返す
+    fn tolerance_frames(
+        &self,
+        timing_type: &TimingType,
+        info: &aviutl2::generic::EditInfo,
+        grid: &Grid,
+        grid_config: &crate::grid::GridConfig,
+        frame_num: f64,
+    ) -> anyhow::Result<f64> {
+        let start = || {
+            self.start_tolerance
+                .frames_at(info, grid, grid_config, frame_num)
+        };
+        let end = || {
+            self.end_tolerance
+                .frames_at(info, grid, grid_config, frame_num)
+        };
+        match timing_type {
+            TimingType::Start { .. } => start(),
+            TimingType::Keyframe { .. } => {
+                self.keyframe_tolerance
+                    .frames_at(info, grid, grid_config, frame_num)
+            }
+            TimingType::End { .. } => end(),
+            // NOTE: 終点と始点が一緒に動くので、対象になっている方の狭い範囲に合わせる
+            TimingType::EndThenStart { .. } => match (self.start, self.end) {
+                (true, true) => Ok(start()?.min(end()?)),
+                (true, false) => start(),
+                _ => end(),
+            },
+        }
+    }
+}
+
+/// 検出に使った設定。補正の直前にズレを検出し直すのに使う
+#[derive(Debug, Clone)]
+pub struct FixContext {
+    pub find_target: FindTarget,
+    pub grid_source: GridSource,
+    pub grid_config: crate::grid::GridConfig,
+}
+
+#[derive(Debug, Clone, Default)]
+pub enum GridSource {
+    #[default]
+    Project,
+    TempoMap(Vec<aviutl2::generic::BpmInfo>),
+    Onsets {
+        /// 音声から検出したオンセット（音声の頭からの秒）
+        onsets: std::sync::Arc<[f64]>,
+        /// タイムライン上で音声が始まるフレーム
+        start_frame: usize,
+    },
+}
+
+enum Grid {
+    Bpm(Vec<aviutl2::generic::BpmInfo>),
+    /// タイムラインの頭からの秒
+    Onsets(Vec<f64>),
+}
+
+impl GridSource {
+    fn resolve(&self, edit: &aviutl2::generic::ReadSection) -> anyhow::Result<Grid> {
+        match self {
+            GridSource::Project => Ok(Grid::Bpm(edit.get_grid_bpm_list()?)),
+            GridSource::TempoMap(bpm_list) => Ok(Grid::Bpm(bpm_list.clone())),
+            GridSource::Onsets {
+                onsets,
+                start_frame,
+            } => {
+                let info = crate::EDIT_HANDLE.get_edit_info();
+                let start_seconds =
+                    *start_frame as f64 * *info.fps.denom() as f64 / *info.fps.numer() as f64;
+                Ok(Grid::Onsets(
+                    onsets.iter().map(|onset| onset + start_seconds).collect(),
+                ))
+            }
+        }
+    }
+}
+
+impl Grid {
+    fn nearest_frame(
+        &self,
+        info: &aviutl2::generic::EditInfo,
+        grid_config: &crate::grid::GridConfig,
+        frame_num: f64,
+        direction: crate::grid::SnapDirection,
+    ) -> anyhow::Result<Option<i32>> {
+        match self {
+            Grid::Bpm(bpm_list) => {
+                crate::grid::nearest_grid_frame(info, bpm_list, grid_config, frame_num, direction)
+            }
+            Grid::Onsets(onsets) => Ok(crate::grid::nearest_onset_frame(
+                info,
+                onsets,
+                frame_num,
+                grid_config.rounding,
+                direction,
+            )),
+        }
+    }
+
+    fn musical_time(
+        &self,
+        info: &aviutl2::generic::EditInfo,
+        grid_config: &crate::grid::GridConfig,
+        frame_num: f64,
+    ) -> Option<crate::grid::MusicalTime> {
+        match self {
+            Grid::Bpm(bpm_list) => {
+                crate::grid::frame_to_musical_time(info, bpm_list, grid_config, frame_num).ok()
+            }
+            Grid::Onsets(_) => None,
+        }
+    }
+}
+
+/// ズレとみなす範囲の単位
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ToleranceUnit {
+    #[default]
+    Frames,
+    Milliseconds,
+    /// 拍に対する割合。BPMが変わる場合はその位置のBPMで換算する
+    Beats,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tolerance {
+    pub value: f64,
+    pub unit: ToleranceUnit,
 }
 
-pub fn max_frames_per_beat() -> anyhow::Result<f64> {
+impl Tolerance {
+    /// 指定したフレームでの許容範囲をフレーム数に換算する
+    fn frames_at(
+        &self,
+        info: &aviutl2::generic::EditInfo,
+        grid: &Grid,
+        grid_config: &crate::grid::GridConfig,
+        frame_num: f64,
+    ) -> anyhow::Result<f64> {
+        let fps = *info.fps.numer() as f64 / *info.fps.denom() as f64;
+        match self.unit {
+            ToleranceUnit::Frames => Ok(self.value),
+            ToleranceUnit::Milliseconds => Ok(self.value / 1000.0 * fps),
+            ToleranceUnit::Beats => {
+                let frames_per_beat = match grid {
+                    Grid::Bpm(bpm_list) => {
+                        crate::grid::frames_per_beat_at(info, bpm_list, grid_config, frame_num)?
+                    }
+                    // NOTE: オンセットの場合は前後のオンセットの間隔を1拍とみなす
+                    Grid::Onsets(onsets) => {
+                        crate::grid::onset_interval_frames_at(info, onsets, frame_num)?
+                    }
+                };
+                Ok(self.value * frames_per_beat)
+            }
+        }
+    }
+}
+
+/// 指定した単位で設定できる許容範囲の最大値
+pub fn max_tolerance(
+    grid_source: &GridSource,
+    grid_config: &crate::grid::GridConfig,
+    unit: ToleranceUnit,
+) -> anyhow::Result<f64> {
     crate::EDIT_HANDLE.call_read_section(|edit| {
         let info = crate::EDIT_HANDLE.get_edit_info();
-        let bpm_list = edit.get_grid_bpm_list()?;
-        crate::grid::max_frames_per_beat(&info, &bpm_list)
+        let fps = *info.fps.numer() as f64 / *info.fps.denom() as f64;
+        let (max_frames, max_beats) = match grid_source.resolve(edit)? {
+            Grid::Bpm(bpm_list) => (
+                crate::grid::max_frames_per_beat(&info, &bpm_list)?
+                    * grid_config.min_beat_interval(&bpm_list)
+                    / 2.0,
+                grid_config.min_beat_interval(&bpm_list) / 2.0,
+            ),
+            Grid::Onsets(onsets) => (
+                crate::grid::max_onset_interval_frames(&info, &onsets)? / 2.0,
+                0.5,
+            ),
+        };
+        Ok(match unit {
+            ToleranceUnit::Frames => max_frames,
+            ToleranceUnit::Milliseconds => max_frames / fps * 1000.0,
+            ToleranceUnit::Beats => max_beats,
+        })
     })?
 }
 
@@ -27,7 +268,66 @@ pub struct OffbeatInfo {
     pub layer_name: String,
     pub position: aviutl2::generic::ObjectLayerFrame,
     pub frame: usize,
+    pub musical_time: Option<crate::grid::MusicalTime>,
+    /// 同じレイヤーで隣とつながって並んでいるオブジェクト（チェーン）の先頭のオブジェクト。
+    /// 隣とつながっていない場合はNone
+    pub chain: Option<ObjectHandle>,
+    /// オブジェクトごと動かすときに、一緒にずれる後ろのオブジェクトの境界のうち、
+    /// この補正でまとめてグリッドに合わせるもの
+    pub chain_boundaries: Vec<OffbeatInfo>,
+    /// そのままではグリッドに合わせられない場合はSome
+    pub blocked: Option<Blocked>,
 }
+
+/// そのままではグリッドに合わせられない理由
+#[derive(Debug, Clone)]
+pub enum Blocked {
+    /// グリッドに合わせると同じレイヤーの隣のタイミングにぶつかる
+    Neighbour {
+        side: NeighbourSide,
+        /// ぶつかる隣のタイミング
+        neighbour: Box<OffbeatInfo>,
+        /// 反対側の隣のタイミングのフレーム（同じレイヤーにある場合）
+        opposite_frame: Option<usize>,
+    },
+    /// 境界の開始位置と終了位置の向きの制約が両立せず、合わせられる向きがない
+    DirectionConflict,
+}
+
+impl std::fmt::Display for Blocked {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Blocked::Neighbour { neighbour, .. } => {
+                write!(
+                    f,
+                    "Blocked by the neighbouring timing at {}f",
+                    neighbour.frame
+                )
+            }
+            Blocked::DirectionConflict => {
+                write!(f, "Start and end direction constraints conflict")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NeighbourSide {
+    Previous,
+    Next,
+}
+
+/// 隣とぶつかるズレの補正のしかた
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockResolution {
+    /// 隣とぶつからない一番近いグリッドに合わせる
+    NextGridLine,
+    /// 隣のタイミングだけをぶつからない位置まで動かす
+    ShortenNeighbour,
+    /// 隣のオブジェクトを長さを変えずにぶつからない位置まで動かす
+    PushNeighbour,
+}
+
 #[derive(Debug, Clone)]
 pub enum TimingType {
     Start {
@@ -44,156 +344,794 @@ pub enum TimingType {
         object_name_left: String,
         object_handle_left: ObjectHandle,
         object_name_right: String,
+        /// 左のオブジェクトの終点と右のオブジェクトの始点の間の隙間（フレーム）。補正すると詰める
+        gap_frames: usize,
     },
 }
 
+/// join_gapsのときは、これ以下の隙間しかない隣のオブジェクトもつながっているとみなす
+// NOTE: 1〜2フレームの隙間はたいてい置き間違いなので、境界として扱って補正で詰める
+pub const MAX_JOIN_GAP_FRAMES: usize = 2;
+
 pub fn find_offsync_objects(
     find_target: &FindTarget,
-    distance: usize,
+    grid_source: &GridSource,
+    grid_config: &crate::grid::GridConfig,
 ) -> anyhow::Result<Vec<OffbeatInfo>> {
-    crate::EDIT_HANDLE.call_edit_section(|edit| {
-        let bpm_list = edit.get_grid_bpm_list()?;
-        let mut all_timings = Vec::new();
-        for layer in edit.layers() {
-            let layer_name = layer.get_name()?.unwrap_or_else(|| {
-                format!(
-                    "{}{}",
-                    aviutl2::config::get_language_text("Name", "Layer"),
-                    layer.index + 1
-                )
-            });
-            for (position, object) in layer.objects() {
-                let alias = edit.object(object).get_alias_parsed()?;
-
-                let frames: Vec<usize> = alias
-                    .get_table("Object")
-                    .context("Object table not found")?
-                    .parse_value("frame")
-                    .context("frame column not found")??;
-
-                let object_name = get_object_name(&alias)?;
-
-                for (i, &frame) in frames.iter().enumerate() {
-                    all_timings.push(OffbeatInfo {
-                        frame,
-                        timing_type: if i == 0 {
-                            TimingType::Start {
-                                object_name: object_name.clone(),
-                            }
-                        } else if i > 0 && i < frames.len() - 1 {
-                            TimingType::Keyframe {
-                                object_name: object_name.clone(),
-                                keyframe_index: i - 1,
-                            }
-                        } else if i == frames.len() - 1 {
-                            TimingType::End {
-                                object_name: object_name.clone(),
+    crate::EDIT_HANDLE.call_read_section(|edit| {
+        let info = crate::EDIT_HANDLE.get_edit_info();
+        let grid = grid_source.resolve(edit)?;
+        detect(
+            edit,
+            &info,
+            0..=info.layer_max,
+            &grid,
+            find_target,
+            grid_config,
+        )
+    })?
+}
+
+/// 指定したレイヤーのズレを検出する。レイヤーをまたいだ判定はないので、
+/// 一部のレイヤーだけを検出しても、そのレイヤーの結果は全体を検出したときと同じになる
+fn detect(
+    edit: &aviutl2::generic::ReadSection,
+    info: &aviutl2::generic::EditInfo,
+    layers: impl IntoIterator<Item = usize>,
+    grid: &Grid,
+    find_target: &FindTarget,
+    grid_config: &crate::grid::GridConfig,
+) -> anyhow::Result<Vec<OffbeatInfo>> {
+    match find_target.length_note {
+        None => {
+            let joined_timings =
+                collect_joined_timings(edit, layers, find_target.max_join_gap_frames())?;
+            detect_offsync(info, &joined_timings, grid, find_target, grid_config)
+        }
+        Some(note) => {
+            let Grid::Bpm(bpm_list) = grid else {
+                anyhow::bail!("Object lengths can only be checked against a BPM grid");
+            };
+            let timings = with_ignored(edit, collect_timings(edit, layers)?)?;
+            detect_length_offsync(
+                info,
+                &timings,
+                grid,
+                bpm_list,
+                note,
+                find_target,
+                grid_config,
+            )
+        }
+    }
+}
+
+/// オフセットを合わせたBPMグリッドと、その前後のズレの件数
+#[derive(Debug, Clone)]
+pub struct GridOffsetFit {
+    pub bpm_list: Vec<aviutl2::generic::BpmInfo>,
+    pub before_count: usize,
+    pub after_count: usize,
+}
+
+// NOTE: グリッドはフレームに丸められるので、1フレームより細かく動かして探す
+const FIT_STEPS_PER_FRAME: f64 = 4.0;
+
+/// グリッドのオフセットを合わせるために、プロジェクトから読み取ったもの。
+/// 合わせる計算は時間がかかるので、GUIを止めないように別スレッドでrunする
+pub struct GridOffsetFitJob {
+    info: aviutl2::generic::EditInfo,
+    bpm_list: Vec<aviutl2::generic::BpmInfo>,
+    joined_timings: Vec<JoinedTiming>,
+    find_target: FindTarget,
+    grid_config: crate::grid::GridConfig,
+}
+
+pub fn prepare_grid_offset_fit(
+    find_target: &FindTarget,
+    grid_source: &GridSource,
+    grid_config: &crate::grid::GridConfig,
+) -> anyhow::Result<GridOffsetFitJob> {
+    // NOTE: 長さはオフセットをずらしても変わらないので、合わせるものがない
+    if find_target.length_note.is_some() {
+        anyhow::bail!("Grid offset cannot be fitted when checking lengths");
+    }
+    crate::EDIT_HANDLE.call_read_section(|edit| {
+        let info = crate::EDIT_HANDLE.get_edit_info();
+        let Grid::Bpm(mut bpm_list) = grid_source.resolve(edit)? else {
+            anyhow::bail!("Grid offset can only be fitted to a BPM grid");
+        };
+        // NOTE: BpmGridSegmentsの区間の順番と合わせる
+        bpm_list.sort_by(|left, right| left.start.total_cmp(&right.start));
+        let joined_timings =
+            collect_joined_timings(edit, 0..=info.layer_max, find_target.max_join_gap_frames())?;
+        Ok(GridOffsetFitJob {
+            info,
+            bpm_list,
+            joined_timings,
+            find_target: find_target.clone(),
+            grid_config: grid_config.clone(),
+        })
+    })?
+}
+
+impl GridOffsetFitJob {
+    /// テンポはそのままに、各BPM情報のオフセットをズレの合計が最小になるようにずらす
+    pub fn run(self) -> anyhow::Result<GridOffsetFit> {
+        let Self {
+            info,
+            mut bpm_list,
+            joined_timings,
+            find_target,
+            grid_config,
+        } = self;
+        let count_offsync = |bpm_list: &[aviutl2::generic::BpmInfo]| {
+            detect_offsync(
+                &info,
+                &joined_timings,
+                &Grid::Bpm(bpm_list.to_vec()),
+                &find_target,
+                &grid_config,
+            )
+            .map(|result| result.iter().filter(|info| info.blocked.is_none()).count())
+        };
+        // NOTE: 許容フレーム数で絞ると、大きくずらして全部を範囲外にするのが最小になってしまうので、
+        //       ズレの合計はすべての境界について測る。オフセットごとに何度も測るので、
+        //       グリッドに関係ない部分は先に求めておき、ズレだけを数える
+        let candidates = offsync_candidates(&info, &joined_timings, &find_target);
+        let bar_config = downbeat_config(&find_target, &grid_config);
+        let mut segments = crate::grid::BpmGridSegments::new(&info, &bpm_list, &grid_config)?;
+        let mut bar_segments = bar_config
+            .as_ref()
+            .map(|bar_config| crate::grid::BpmGridSegments::new(&info, &bpm_list, bar_config))
+            .transpose()?;
+
+        let before_count = count_offsync(&bpm_list)?;
+        let fps = *info.fps.numer() as f64 / *info.fps.denom() as f64;
+        let step_seconds = 1.0 / fps / FIT_STEPS_PER_FRAME;
+        let period_of = |bpm: &aviutl2::generic::BpmInfo| {
+            let beats_per_period = if grid_config.snap_to_bar {
+                bpm.beat.max(1) as f64
+            } else {
+                1.0
+            };
+            60.0 / bpm.tempo as f64 * beats_per_period
+        };
+        // NOTE: 隣の区間の境界でも、1周期以内ならこの区間のグリッドが一番近くなりうる
+        let margin_frames = bpm_list.iter().map(period_of).fold(0.0, f64::max) * fps;
+        for (index, bpm) in bpm_list.iter_mut().enumerate() {
+            let original_offset = bpm.offset as f64;
+            let max_step = (period_of(bpm) / 2.0 / step_seconds).floor() as i64;
+
+            // NOTE: この区間のオフセットで変わるのは、この区間の近くの境界だけなので、それだけを測る。
+            //       測るフレームと向きは候補ごとに決まっているので、ほかの区間で一番近いグリッドは
+            //       先に求めておく
+            let (start_frame, end_frame) = segments.frame_range(index);
+            let targets: Vec<_> = candidates
+                .iter()
+                .filter_map(|candidate| {
+                    let timing = &joined_timings[candidate.index].timing;
+                    let frame = (timing.frame as i64 + candidate.end_offset) as f64;
+                    let nearby = frame >= start_frame - margin_frames
+                        && end_frame.is_none_or(|end_frame| frame < end_frame + margin_frames);
+                    nearby.then(|| {
+                        let other = segments.nearest_frame(frame, candidate.direction, Some(index));
+                        let other_bar = bar_segments.as_ref().and_then(|bar_segments| {
+                            bar_segments.nearest_frame(frame, candidate.direction, Some(index))
+                        });
+                        (candidate, timing, other, other_bar)
+                    })
+                })
+                .collect();
+            let total_offset =
+                |segments: &crate::grid::BpmGridSegments,
+                 bar_segments: Option<&crate::grid::BpmGridSegments>| {
+                    let mut total = 0;
+                    for &(candidate, timing, other, other_bar) in &targets {
+                        let nearest = |frame: f64, direction: crate::grid::SnapDirection| {
+                            anyhow::Ok(
+                                crate::grid::SegmentFrame::nearer(
+                                    other,
+                                    segments.nearest_in_segment(index, frame, direction),
+                                )
+                                .map(|nearest| nearest.frame()),
+                            )
+                        };
+                        let nearest_bar = bar_segments.map(|bar_segments| {
+                            move |frame: f64, direction: crate::grid::SnapDirection| {
+                                anyhow::Ok(
+                                    crate::grid::SegmentFrame::nearer(
+                                        other_bar,
+                                        bar_segments.nearest_in_segment(index, frame, direction),
+                                    )
+                                    .map(|nearest| nearest.frame()),
+                                )
                             }
-                        } else {
-                            unreachable!()
-                        },
-                        object,
-                        layer_name: layer_name.clone(),
-                        position,
-                        offset_frames: 0,
-                    });
+                        });
+                        let Some((offset_frames, new_frame)) =
+                            measure_offsync(timing, candidate, nearest, nearest_bar)?
+                        else {
+                            continue;
+                        };
+                        if !candidate.direction_conflict
+                            && candidate.blocked_side(&joined_timings, new_frame).is_none()
+                        {
+                            total += offset_frames.unsigned_abs();
+                        }
+                    }
+                    anyhow::Ok(total)
+                };
+            let mut best_total = total_offset(&segments, bar_segments.as_ref())?;
+            let mut best_step = 0i64;
+            for step in (-max_step..=max_step).filter(|&step| step != 0) {
+                let offset = (original_offset + step as f64 * step_seconds) as f32;
+                segments.set_offset(index, offset);
+                if let Some(bar_segments) = &mut bar_segments {
+                    bar_segments.set_offset(index, offset);
                 }
+                let total = total_offset(&segments, bar_segments.as_ref())?;
+                if total < best_total || (total == best_total && step.abs() < best_step.abs()) {
+                    best_total = total;
+                    best_step = step;
+                }
+            }
+            let best_offset = (original_offset + best_step as f64 * step_seconds) as f32;
+            segments.set_offset(index, best_offset);
+            if let Some(bar_segments) = &mut bar_segments {
+                bar_segments.set_offset(index, best_offset);
             }
+            bpm.offset = best_offset;
         }
+        let after_count = count_offsync(&bpm_list)?;
 
-        let mut joined_timings = Vec::new();
-        for (i, timing) in all_timings.iter().enumerate() {
-            if i > 0
-                && let TimingType::Start {
-                    object_name: object_name_right,
-                } = &timing.timing_type
-            {
-                let last_timing: &OffbeatInfo = joined_timings.last().unwrap();
-                if timing.position.layer == last_timing.position.layer
-                    && timing.frame == last_timing.frame + 1
-                    && let TimingType::End {
-                        object_name: object_name_left,
-                    } = &last_timing.timing_type
-                {
-                    let object_handle_left = last_timing.object;
-                    let object_name_left = object_name_left.clone();
-                    joined_timings.pop();
-                    joined_timings.push(OffbeatInfo {
-                        timing_type: TimingType::EndThenStart {
-                            object_name_left,
-                            object_handle_left,
-                            object_name_right: object_name_right.clone(),
-                        },
-                        ..timing.clone()
-                    });
-                    continue;
-                }
-            }
+        Ok(GridOffsetFit {
+            bpm_list,
+            before_count,
+            after_count,
+        })
+    }
+}
 
-            joined_timings.push(timing.clone());
-        }
+struct JoinedTiming {
+    timing: OffbeatInfo,
+    ignored: bool,
+}
 
-        let mut result = Vec::new();
-        for (i, timing) in joined_timings.iter().enumerate() {
-            let is_target = match &timing.timing_type {
-                TimingType::Start { .. } => find_target.start,
-                TimingType::Keyframe { .. } => find_target.keyframe,
-                TimingType::End { .. } => find_target.end,
-                TimingType::EndThenStart { .. } => find_target.start || find_target.end,
-            };
-            if !is_target {
-                continue;
-            }
+fn collect_joined_timings(
+    edit: &aviutl2::generic::ReadSection,
+    layers: impl IntoIterator<Item = usize>,
+    max_join_gap: usize,
+) -> anyhow::Result<Vec<JoinedTiming>> {
+    let all_timings = collect_timings(edit, layers)?;
 
-            if !find_target.project_end && timing.frame == edit.info.frame_max {
+    let mut joined_timings = Vec::new();
+    for (i, timing) in all_timings.iter().enumerate() {
+        if i > 0
+            && let TimingType::Start {
+                object_name: object_name_right,
+            } = &timing.timing_type
+        {
+            let last_timing: &OffbeatInfo = joined_timings.last().unwrap();
+            if timing.position.layer == last_timing.position.layer
+                && timing.frame > last_timing.frame
+                && timing.frame - last_timing.frame - 1 <= max_join_gap
+                && let TimingType::End {
+                    object_name: object_name_left,
+                } = &last_timing.timing_type
+            {
+                let object_handle_left = last_timing.object;
+                let object_name_left = object_name_left.clone();
+                let gap_frames = timing.frame - last_timing.frame - 1;
+                joined_timings.pop();
+                joined_timings.push(OffbeatInfo {
+                    timing_type: TimingType::EndThenStart {
+                        object_name_left,
+                        object_handle_left,
+                        object_name_right: object_name_right.clone(),
+                        gap_frames,
+                    },
+                    ..timing.clone()
+                });
                 continue;
             }
+        }
 
-            // NOTE: 終端はBPMグリッドに右に触れる感じで合っていてほしいので、そう補正する
-            let offset = if matches!(timing.timing_type, TimingType::End { .. }) {
-                1
-            } else {
-                0
-            };
-            let adjusted_frame = timing.frame as i64 + offset;
-            let nearest_beat_frame =
-                crate::grid::nearest_grid_frame(&edit.info, &bpm_list, adjusted_frame as f64)?
-                    as usize;
-            let offset_frames = adjusted_frame - nearest_beat_frame as i64;
-            let adjusted_nearest_beat_frame = nearest_beat_frame as i64 - offset;
-            if offset_frames.unsigned_abs() as usize > distance || offset_frames == 0 {
-                continue;
-            }
+        joined_timings.push(timing.clone());
+    }
+    assign_chains(&mut joined_timings);
 
-            if edit.count_object_effect(timing.object, crate::marker::IGNORE_MARKER_NAME)? > 0 {
-                continue;
+    with_ignored(edit, joined_timings)
+}
+
+/// つながっているオブジェクトの列ごとに、先頭のオブジェクトを記録する
+fn assign_chains(joined_timings: &mut [OffbeatInfo]) {
+    let mut heads = std::collections::HashMap::new();
+    for timing in joined_timings.iter() {
+        match &timing.timing_type {
+            TimingType::Start { .. } => {
+                heads.insert(timing.object, timing.object);
             }
+            TimingType::EndThenStart {
+                object_handle_left, ..
+            } => {
+                let head = heads
+                    .get(object_handle_left)
+                    .copied()
+                    .unwrap_or(*object_handle_left);
+                heads.insert(*object_handle_left, head);
+                heads.insert(timing.object, head);
+            }
+            _ => {}
+        }
+    }
+    let mut chain_lengths = std::collections::HashMap::new();
+    for head in heads.values() {
+        *chain_lengths.entry(*head).or_insert(0usize) += 1;
+    }
+    for timing in joined_timings.iter_mut() {
+        timing.chain = heads
+            .get(&timing.object)
+            .copied()
+            .filter(|head| chain_lengths[head] > 1);
+    }
+}
 
-            if i > 0 {
-                let prev_timing = &joined_timings[i - 1];
-                if prev_timing.position.layer == timing.position.layer
-                    && adjusted_nearest_beat_frame <= (prev_timing.frame as i64)
-                {
-                    continue;
-                }
+fn with_ignored(
+    edit: &aviutl2::generic::ReadSection,
+    timings: Vec<OffbeatInfo>,
+) -> anyhow::Result<Vec<JoinedTiming>> {
+    timings
+        .into_iter()
+        .map(|timing| {
+            Ok(JoinedTiming {
+                ignored: edit
+                    .count_object_effect(timing.object, crate::marker::IGNORE_MARKER_NAME)?
+                    > 0,
+                timing,
+            })
+        })
+        .collect()
+}
+
+/// グリッドに関係なく決まる、1つのタイミングの検出の条件
+struct OffsyncCandidate {
+    index: usize,
+    direction: crate::grid::SnapDirection,
+    /// 境界の開始位置と終了位置の向きの制約が両立しない。その場合は一番近いグリッドで測る
+    direction_conflict: bool,
+    /// 終端は次のフレームをグリッドに合わせるので1
+    end_offset: i64,
+    /// オブジェクトごと動かすときの、終点までの長さ
+    span: i64,
+    /// 境界は左のオブジェクトの終点が1フレーム前に来るので1
+    lead: i64,
+    gap_frames: usize,
+    prev: Option<usize>,
+    next: Option<usize>,
+}
+
+impl OffsyncCandidate {
+    /// new_frameに合わせたときに、同じレイヤーの隣とぶつかる側
+    fn blocked_side(
+        &self,
+        joined_timings: &[JoinedTiming],
+        new_frame: i64,
+    ) -> Option<NeighbourSide> {
+        if let Some(prev) = self.prev
+            && new_frame - self.lead <= joined_timings[prev].timing.frame as i64
+        {
+            Some(NeighbourSide::Previous)
+        } else if let Some(next) = self.next
+            && new_frame + self.span >= joined_timings[next].timing.frame as i64
+        {
+            Some(NeighbourSide::Next)
+        } else {
+            None
+        }
+    }
+}
+
+fn offsync_candidates(
+    info: &aviutl2::generic::EditInfo,
+    joined_timings: &[JoinedTiming],
+    find_target: &FindTarget,
+) -> Vec<OffsyncCandidate> {
+    let mut candidates = Vec::new();
+    for (i, joined_timing) in joined_timings.iter().enumerate() {
+        let timing = &joined_timing.timing;
+        let is_target = match &timing.timing_type {
+            TimingType::Start { .. } => find_target.start,
+            TimingType::Keyframe { .. } => find_target.keyframe,
+            TimingType::End { .. } => find_target.end,
+            TimingType::EndThenStart { .. } => find_target.start || find_target.end,
+        };
+        if !is_target || joined_timing.ignored {
+            continue;
+        }
+        let direction = match &timing.timing_type {
+            TimingType::Start { .. } => Some(find_target.start_direction),
+            TimingType::Keyframe { .. } => Some(find_target.keyframe_direction),
+            TimingType::End { .. } => Some(find_target.end_direction),
+            // NOTE: 終点と始点が一緒に動くので、対象になっている両方の向きの制約を満たす必要がある
+            TimingType::EndThenStart { .. } => match (find_target.start, find_target.end) {
+                (true, true) => find_target
+                    .start_direction
+                    .intersect(find_target.end_direction),
+                (true, false) => Some(find_target.start_direction),
+                _ => Some(find_target.end_direction),
+            },
+        };
+        // NOTE: 合わせられる向きがない境界も、ずれていることがわかるように結果に含める
+        let direction_conflict = direction.is_none();
+        let direction = direction.unwrap_or(crate::grid::SnapDirection::Nearest);
+
+        if !find_target.project_end && timing.frame == info.frame_max {
+            continue;
+        }
+
+        // NOTE: 終端はBPMグリッドに右に触れる感じで合っていてほしいので、そう補正する
+        let end_offset = if matches!(timing.timing_type, TimingType::End { .. }) {
+            1
+        } else {
+            0
+        };
+        // NOTE: オブジェクトごと動かすときは、後ろにつながっているオブジェクトの終点までの長さを含めて
+        //       ぶつかるかを見る
+        let span = if find_target.moves_whole_object(&timing.timing_type) {
+            let chain_end = chain_followers(joined_timings, i)
+                .map(|follower| follower.position.end)
+                .fold(timing.position.end, usize::max);
+            (chain_end - timing.frame) as i64
+        } else {
+            0
+        };
+        let lead = if matches!(timing.timing_type, TimingType::EndThenStart { .. }) {
+            1
+        } else {
+            0
+        };
+        let gap_frames = match &timing.timing_type {
+            TimingType::EndThenStart { gap_frames, .. } => *gap_frames,
+            _ => 0,
+        };
+        let prev = i
+            .checked_sub(1)
+            .filter(|&prev| joined_timings[prev].timing.position.layer == timing.position.layer);
+        let next = (i + 1..joined_timings.len())
+            .find(|&next| joined_timings[next].timing.frame as i64 > timing.frame as i64 + span)
+            .filter(|&next| joined_timings[next].timing.position.layer == timing.position.layer);
+        candidates.push(OffsyncCandidate {
+            index: i,
+            direction,
+            direction_conflict,
+            end_offset,
+            span,
+            lead,
+            gap_frames,
+            prev,
+            next,
+        });
+    }
+    candidates
+}
+
+/// オブジェクトごと動かすときに一緒にずれる、同じチェーンで後ろにつながっているオブジェクトのタイミング
+fn chain_followers(
+    joined_timings: &[JoinedTiming],
+    index: usize,
+) -> impl Iterator<Item = &OffbeatInfo> {
+    let timing = &joined_timings[index].timing;
+    joined_timings[index + 1..]
+        .iter()
+        .map(|joined_timing| &joined_timing.timing)
+        .take_while(move |follower| follower.position.layer == timing.position.layer)
+        .filter(move |follower| {
+            timing.chain.is_some()
+                && follower.chain == timing.chain
+                && follower.position.start > timing.frame
+        })
+}
+
+/// 小節線付近のみを探すときの、小節線だけのグリッドの設定。小節線にスナップしているときはNone
+fn downbeat_config(
+    find_target: &FindTarget,
+    grid_config: &crate::grid::GridConfig,
+) -> Option<crate::grid::GridConfig> {
+    (find_target.downbeat_only && !grid_config.snap_to_bar).then(|| crate::grid::GridConfig {
+        snap_to_bar: true,
+        ..grid_config.clone()
+    })
+}
+
+/// 候補をグリッドに合わせたときの（ズレ, 合わせた後のフレーム）。小節線付近のみで外れるものはNone。
+///
+/// nearestは一番近いグリッドの求め方、nearest_barは小節線付近のみを探すときの一番近い小節線の求め方
+fn measure_offsync(
+    timing: &OffbeatInfo,
+    candidate: &OffsyncCandidate,
+    nearest: impl Fn(f64, crate::grid::SnapDirection) -> anyhow::Result<Option<i32>>,
+    nearest_bar: Option<impl Fn(f64, crate::grid::SnapDirection) -> anyhow::Result<Option<i32>>>,
+) -> anyhow::Result<Option<(i64, i64)>> {
+    let adjusted_frame = timing.frame as i64 + candidate.end_offset;
+    let Some(nearest_beat_frame) = nearest(adjusted_frame as f64, candidate.direction)? else {
+        return Ok(None);
+    };
+    // NOTE: 小節線付近かを見るのは、場面の切り替わりになる開始位置だけ
+    if let Some(nearest_bar) = nearest_bar
+        && matches!(
+            timing.timing_type,
+            TimingType::Start { .. } | TimingType::EndThenStart { .. }
+        )
+        && nearest_bar(adjusted_frame as f64, candidate.direction)? != Some(nearest_beat_frame)
+    {
+        return Ok(None);
+    }
+    Ok(Some((
+        adjusted_frame - nearest_beat_frame as i64,
+        nearest_beat_frame as i64 - candidate.end_offset,
+    )))
+}
+
+fn detect_offsync(
+    info: &aviutl2::generic::EditInfo,
+    joined_timings: &[JoinedTiming],
+    grid: &Grid,
+    find_target: &FindTarget,
+    grid_config: &crate::grid::GridConfig,
+) -> anyhow::Result<Vec<OffbeatInfo>> {
+    let nearest = |frame: f64, direction: crate::grid::SnapDirection| {
+        grid.nearest_frame(info, grid_config, frame, direction)
+    };
+    let bar_config = downbeat_config(find_target, grid_config);
+    let nearest_bar = match (grid, &bar_config) {
+        (Grid::Bpm(bpm_list), Some(bar_config)) => {
+            Some(move |frame: f64, direction: crate::grid::SnapDirection| {
+                crate::grid::nearest_grid_frame(info, bpm_list, bar_config, frame, direction)
+            })
+        }
+        _ => None,
+    };
+    let mut result: Vec<OffbeatInfo> = Vec::new();
+    let mut whole_moved = std::collections::HashSet::new();
+    // 一緒にずれる後ろのオブジェクトから、それをずらす補正のresultでの位置
+    let mut chain_heads: std::collections::HashMap<ObjectHandle, usize> =
+        std::collections::HashMap::new();
+    for candidate in offsync_candidates(info, joined_timings, find_target) {
+        let timing = &joined_timings[candidate.index].timing;
+        let Some((offset_frames, new_frame)) =
+            measure_offsync(timing, &candidate, nearest, nearest_bar)?
+        else {
+            continue;
+        };
+        let tolerance_frames = find_target.tolerance_frames(
+            &timing.timing_type,
+            info,
+            grid,
+            grid_config,
+            (timing.frame as i64 + candidate.end_offset) as f64,
+        )?;
+        // NOTE: 隙間のある境界は、グリッドに合っていても隙間を詰めるために含める
+        if offset_frames.unsigned_abs() as f64 > tolerance_frames
+            || (offset_frames.unsigned_abs() < find_target.min_offset_frames.max(1)
+                && candidate.gap_frames == 0)
+        {
+            continue;
+        }
+
+        let prev_timing = candidate.prev.map(|prev| &joined_timings[prev].timing);
+        let next_timing = candidate.next.map(|next| &joined_timings[next].timing);
+        // NOTE: ぶつかるものも、補正のしかたを選べるように結果に含める
+        let blocked = if candidate.direction_conflict {
+            Some(Blocked::DirectionConflict)
+        } else {
+            candidate
+                .blocked_side(joined_timings, new_frame)
+                .map(|side| match side {
+                    NeighbourSide::Previous => Blocked::Neighbour {
+                        side,
+                        neighbour: Box::new(prev_timing.unwrap().clone()),
+                        opposite_frame: next_timing.map(|next_timing| next_timing.frame),
+                    },
+                    NeighbourSide::Next => Blocked::Neighbour {
+                        side,
+                        neighbour: Box::new(next_timing.unwrap().clone()),
+                        opposite_frame: prev_timing.map(|prev_timing| prev_timing.frame),
+                    },
+                })
+        };
+
+        let offbeat_info = OffbeatInfo {
+            offset_frames,
+            musical_time: grid.musical_time(info, grid_config, timing.frame as f64),
+            blocked,
+            ..timing.clone()
+        };
+        let moves_whole_object = find_target.moves_whole_object(&timing.timing_type);
+        // NOTE: 前の境界の補正で一緒にずれるオブジェクトの境界は、別に補正するとその補正と
+        //       同じオブジェクトを動かすので、前の境界の補正でまとめてグリッドに合わせる。
+        //       ぶつかるものはまとめて合わせられないので、前の境界の補正の後にもう一度検出する
+        if moves_whole_object && whole_moved.contains(&timing.object) {
+            if let Some(&head) = chain_heads.get(&timing.object)
+                && offbeat_info.blocked.is_none()
+            {
+                result[head].chain_boundaries.push(offbeat_info);
             }
-            if i < joined_timings.len() - 1 {
-                let next_timing = &joined_timings[i + 1];
-                if next_timing.position.layer == timing.position.layer
-                    && adjusted_nearest_beat_frame >= (next_timing.frame as i64)
-                {
-                    continue;
-                }
+            continue;
+        }
+        // NOTE: 向きの制約が両立しない境界は補正しないので、後ろのオブジェクトもずれない
+        if moves_whole_object && !matches!(offbeat_info.blocked, Some(Blocked::DirectionConflict)) {
+            whole_moved.insert(timing.object);
+            for follower in chain_followers(joined_timings, candidate.index) {
+                whole_moved.insert(follower.object);
+                chain_heads.insert(follower.object, result.len());
             }
+        }
+        result.push(offbeat_info);
+    }
 
-            result.push(OffbeatInfo {
-                offset_frames,
-                ..timing.clone()
-            });
+    // NOTE: オブジェクトごと動かすときは、中間点と終了位置も開始位置と一緒に動くので、
+    //       開始位置を補正するオブジェクトと、一緒にずれる後ろのオブジェクトの中間点と終了位置は
+    //       別に補正しない
+    result.retain(|offbeat_info| {
+        !matches!(
+            offbeat_info.timing_type,
+            TimingType::Keyframe { .. } | TimingType::End { .. }
+        ) || !whole_moved.contains(&offbeat_info.object)
+    });
+    // NOTE: 中間点を伸縮するときは、開始位置か終了位置を補正するオブジェクトの中間点は
+    //       伸縮で動くので、別に補正しない
+    let stretched: std::collections::HashSet<ObjectHandle> = result
+        .iter()
+        .filter(|offbeat_info| {
+            offbeat_info.blocked.is_none()
+                && find_target.stretches_keyframes(&offbeat_info.timing_type)
+        })
+        .flat_map(timing_objects)
+        .collect();
+    result.retain(|offbeat_info| {
+        !matches!(offbeat_info.timing_type, TimingType::Keyframe { .. })
+            || !stretched.contains(&offbeat_info.object)
+    });
+
+    Ok(result)
+}
+
+/// オブジェクトの長さ（開始位置から終了位置の次のフレームまで）が音価の整数倍になっていないものを探す。
+/// ズレは終了位置のズレとして返す
+fn detect_length_offsync(
+    info: &aviutl2::generic::EditInfo,
+    timings: &[JoinedTiming],
+    grid: &Grid,
+    bpm_list: &[aviutl2::generic::BpmInfo],
+    note: crate::grid::NoteValue,
+    find_target: &FindTarget,
+    grid_config: &crate::grid::GridConfig,
+) -> anyhow::Result<Vec<OffbeatInfo>> {
+    // NOTE: 長さは終了位置を動かして揃えるので、終了位置を対象にしていないときは探さない
+    if !find_target.end {
+        return Ok(Vec::new());
+    }
+    let mut result = Vec::new();
+    for (i, joined_timing) in timings.iter().enumerate() {
+        let timing = &joined_timing.timing;
+        if !matches!(timing.timing_type, TimingType::End { .. }) || joined_timing.ignored {
+            continue;
+        }
+        if !find_target.project_end && timing.frame == info.frame_max {
+            continue;
         }
 
-        anyhow::Ok(result)
-    })?
+        let end_frame = timing.frame as i64 + 1;
+        let quantized_end_frame = crate::grid::quantized_length_end(
+            info,
+            bpm_list,
+            grid_config,
+            timing.position.start as f64..end_frame as f64,
+            note,
+            find_target.end_direction,
+        )? as i64;
+        let offset_frames = end_frame - quantized_end_frame;
+        let tolerance_frames =
+            find_target
+                .end_tolerance
+                .frames_at(info, grid, grid_config, end_frame as f64)?;
+        if offset_frames.unsigned_abs() as f64 > tolerance_frames
+            || offset_frames.unsigned_abs() < find_target.min_offset_frames.max(1)
+        {
+            continue;
+        }
+
+        let new_frame = quantized_end_frame - 1;
+        // NOTE: 終了位置の前には必ず同じオブジェクトの開始位置か中間点がある
+        let prev_timing = &timings[i - 1].timing;
+        let next_timing = timings
+            .get(i + 1)
+            .map(|next| &next.timing)
+            .filter(|next_timing| next_timing.position.layer == timing.position.layer);
+        let blocked = if new_frame <= prev_timing.frame as i64 {
+            Some(Blocked::Neighbour {
+                side: NeighbourSide::Previous,
+                neighbour: Box::new(prev_timing.clone()),
+                opposite_frame: next_timing.map(|next_timing| next_timing.frame),
+            })
+        } else if let Some(next_timing) = next_timing
+            && new_frame >= next_timing.frame as i64
+        {
+            Some(Blocked::Neighbour {
+                side: NeighbourSide::Next,
+                neighbour: Box::new(next_timing.clone()),
+                opposite_frame: Some(prev_timing.frame),
+            })
+        } else {
+            None
+        };
+
+        result.push(OffbeatInfo {
+            offset_frames,
+            musical_time: grid.musical_time(info, grid_config, timing.frame as f64),
+            blocked,
+            ..timing.clone()
+        });
+    }
+    Ok(result)
+}
+
+fn collect_timings(
+    edit: &aviutl2::generic::ReadSection,
+    layers: impl IntoIterator<Item = usize>,
+) -> anyhow::Result<Vec<OffbeatInfo>> {
+    let mut all_timings = Vec::new();
+    for layer in layers {
+        let layer_name = edit.get_layer_name(layer)?.unwrap_or_else(|| {
+            format!(
+                "{}{}",
+                aviutl2::config::get_language_text("Name", "Layer"),
+                layer + 1
+            )
+        });
+        for (position, object) in edit.objects_in_layer(layer) {
+            let alias = edit.object(object).get_alias_parsed()?;
+
+            let frames: Vec<usize> = alias
+                .get_table("Object")
+                .context("Object table not found")?
+                .parse_value("frame")
+                .context("frame column not found")??;
+
+            let object_name = get_object_name(&alias)?;
+
+            for (i, &frame) in frames.iter().enumerate() {
+                all_timings.push(OffbeatInfo {
+                    frame,
+                    timing_type: if i == 0 {
+                        TimingType::Start {
+                            object_name: object_name.clone(),
+                        }
+                    } else if i > 0 && i < frames.len() - 1 {
+                        TimingType::Keyframe {
+                            object_name: object_name.clone(),
+                            keyframe_index: i - 1,
+                        }
+                    } else if i == frames.len() - 1 {
+                        TimingType::End {
+                            object_name: object_name.clone(),
+                        }
+                    } else {
+                        unreachable!()
+                    },
+                    object,
+                    layer_name: layer_name.clone(),
+                    position,
+                    offset_frames: 0,
+                    musical_time: None,
+                    chain: None,
+                    chain_boundaries: Vec::new(),
+                    blocked: None,
+                });
+            }
+        }
+    }
+    Ok(all_timings)
 }
 
 fn get_object_name(alias: &aviutl2::alias::Table) -> anyhow::Result<String> {
@@ -230,81 +1168,965 @@ fn get_object_name(alias: &aviutl2::alias::Table) -> anyhow::Result<String> {
     Ok(effect_translated_name)
 }
 
-pub fn fix_offbeat(offbeat_info: &OffbeatInfo) -> anyhow::Result<()> {
-    crate::EDIT_HANDLE.call_edit_section(|edit| {
-        let object = edit.object(offbeat_info.object);
-        match &offbeat_info.timing_type {
-            TimingType::Start { .. } => {
-                let position = object.get_layer_frame()?;
-                edit.move_object_section(
-                    *object,
-                    0,
-                    (position.start as i64 - offbeat_info.offset_frames)
-                        .try_into()
-                        .context("fixed frame out of range")?,
-                )?;
-            }
-            TimingType::End { .. } => {
-                let position = object.get_layer_frame()?;
-                edit.move_object_section(
-                    *object,
-                    object.get_section_num()?,
-                    (position.end as i64 - offbeat_info.offset_frames)
-                        .try_into()
-                        .context("fixed frame out of range")?,
-                )?;
-            }
-            TimingType::Keyframe { keyframe_index, .. } => {
-                let position = object.get_section_frame(*keyframe_index + 1)?;
-                edit.move_object_section(
-                    *object,
-                    *keyframe_index + 1,
-                    (position as i64 - offbeat_info.offset_frames)
-                        .try_into()
-                        .context("fixed frame out of range")?,
-                )?;
+/// ズレを補正すると動くオブジェクト。境界の場合は左右の両方
+fn timing_objects(offbeat_info: &OffbeatInfo) -> Vec<ObjectHandle> {
+    match &offbeat_info.timing_type {
+        TimingType::EndThenStart {
+            object_handle_left, ..
+        } => vec![*object_handle_left, offbeat_info.object],
+        _ => vec![offbeat_info.object],
+    }
+}
+
+pub type TimingKey = (ObjectHandle, usize);
+
+/// 同じ境界を指すズレを、検出し直した後に見つけるためのキー
+pub fn timing_key(offbeat_info: &OffbeatInfo) -> TimingKey {
+    match &offbeat_info.timing_type {
+        TimingType::Start { .. } | TimingType::EndThenStart { .. } => (offbeat_info.object, 0),
+        TimingType::Keyframe { keyframe_index, .. } => (offbeat_info.object, keyframe_index + 1),
+        TimingType::End { .. } => (offbeat_info.object, usize::MAX),
+    }
+}
+
+/// 補正するズレのオブジェクトが今あるレイヤーだけを検出し直す
+fn detect_current_timings(
+    edit: &aviutl2::generic::ReadSection,
+    grid: &Grid,
+    context: &FixContext,
+    offbeat_infos: &[OffbeatInfo],
+) -> anyhow::Result<std::collections::HashMap<TimingKey, OffbeatInfo>> {
+    let info = crate::EDIT_HANDLE.get_edit_info();
+    let mut layers = std::collections::BTreeSet::new();
+    for offbeat_info in offbeat_infos {
+        for object in timing_objects(offbeat_info) {
+            if edit.object_exists(object) {
+                layers.insert(edit.get_object_layer_frame(object)?.layer);
             }
-            TimingType::EndThenStart {
-                object_handle_left, ..
-            } => {
-                if offbeat_info.offset_frames > 0 {
-                    let position = object.get_layer_frame()?;
-                    let left_position = edit.get_object_layer_frame(*object_handle_left)?;
-                    edit.move_object_section(
-                        *object_handle_left,
-                        edit.get_object_section_num(*object_handle_left)?,
-                        (left_position.end as i64 - offbeat_info.offset_frames)
-                            .try_into()
-                            .context("fixed frame out of range")?,
-                    )?;
-                    edit.move_object_section(
-                        *object,
-                        0,
-                        (position.start as i64 - offbeat_info.offset_frames)
-                            .try_into()
-                            .context("fixed frame out of range")?,
-                    )?;
+        }
+    }
+    let current = detect(
+        edit,
+        &info,
+        layers,
+        grid,
+        &context.find_target,
+        &context.grid_config,
+    )?;
+    Ok(current
+        .into_iter()
+        .map(|offbeat_info| (timing_key(&offbeat_info), offbeat_info))
+        .collect())
+}
+
+/// 検出した後にタイムラインが編集されていても、今の位置とズレで補正するように置き換える。
+/// もうズレとして検出されないものは、その理由を返す
+fn revalidate(
+    edit: &aviutl2::generic::ReadSection,
+    offbeat_info: &OffbeatInfo,
+    current_timings: &std::collections::HashMap<TimingKey, OffbeatInfo>,
+) -> Result<OffbeatInfo, String> {
+    if let Some(current) = current_timings.get(&timing_key(offbeat_info)) {
+        return Ok(current.clone());
+    }
+    let left_exists = match &offbeat_info.timing_type {
+        TimingType::EndThenStart {
+            object_handle_left, ..
+        } => edit.object_exists(*object_handle_left),
+        _ => true,
+    };
+    if !edit.object_exists(offbeat_info.object) || !left_exists {
+        return Err("Object no longer exists".to_string());
+    }
+    Err("No longer detected as off the grid".to_string())
+}
+
+/// 補正で行う区間（0 = 開始位置、区間数 = 終了位置）の移動
+#[derive(Debug, Clone)]
+pub struct PlannedMove {
+    pub object: ObjectHandle,
+    pub section: usize,
+    pub old_frame: usize,
+    pub new_frame: usize,
+    /// trueの場合は開始位置（区間0）の移動で、長さを変えずにオブジェクト全体を動かす
+    pub whole_object: bool,
+}
+
+/// 1つのズレを補正するための移動の一覧。movesは実行する順に並んでいる
+#[derive(Debug, Clone)]
+pub struct FixPlan {
+    /// 渡したズレの一覧でのインデックス
+    pub index: usize,
+    pub offbeat_info: OffbeatInfo,
+    pub moves: Vec<PlannedMove>,
+    /// このプランの伸縮で一緒に補正される、渡したズレの一覧でのインデックス
+    pub covered: Vec<usize>,
+}
+
+/// 一括補正で補正できなかったもの
+#[derive(Debug, Clone)]
+pub struct FixFailure {
+    /// 渡したズレの一覧でのインデックス
+    pub index: usize,
+    pub reason: String,
+    /// 検出した後の編集で、オブジェクトが消えたかズレでなくなった
+    pub outdated: bool,
+}
+
+/// ズレを補正するときの移動を、実際には動かさずに求める。
+/// ズレは今のタイムラインで検出し直したものに置き換わる。
+/// resolutionを渡すと、隣とぶつかるものはそのしかたで補正する
+pub fn plan_fixes(
+    offbeat_infos: &[OffbeatInfo],
+    context: &FixContext,
+    resolution: Option<BlockResolution>,
+) -> anyhow::Result<(Vec<FixPlan>, Vec<FixFailure>)> {
+    crate::EDIT_HANDLE.call_read_section(|edit| {
+        let grid = context.grid_source.resolve(edit)?;
+        let current_timings = detect_current_timings(edit, &grid, context, offbeat_infos)?;
+        let mut plans: Vec<FixPlan> = Vec::new();
+        let mut failures = Vec::new();
+        let find_target = &context.find_target;
+        // NOTE: 伸縮したオブジェクトと、それを伸縮したプランの位置
+        let mut spanned_objects: std::collections::HashMap<ObjectHandle, usize> =
+            std::collections::HashMap::new();
+        let mut claimed = MovedSections::default();
+        for (index, offbeat_info) in offbeat_infos.iter().enumerate() {
+            let offbeat_info = match revalidate(edit, offbeat_info, &current_timings) {
+                Ok(offbeat_info) => offbeat_info,
+                Err(reason) => {
+                    failures.push(FixFailure {
+                        index,
+                        reason,
+                        outdated: true,
+                    });
+                    continue;
+                }
+            };
+            let stretches = offbeat_info.blocked.is_none()
+                && find_target.stretches_keyframes(&offbeat_info.timing_type);
+            // NOTE: つながったオブジェクトの別の境界を補正したときに一緒に伸縮したものは、
+            //       そのプランでまとめて補正する
+            if stretches
+                && let Some(&plan_index) = timing_objects(&offbeat_info)
+                    .iter()
+                    .find_map(|object| spanned_objects.get(object))
+            {
+                plans[plan_index].covered.push(index);
+                continue;
+            }
+            let planned = match resolution {
+                Some(resolution) if offbeat_info.blocked.is_some() => {
+                    plan_blocked_fix(edit, &grid, context, &offbeat_info, resolution)
+                        .map(|moves| (moves, Vec::new()))
+                }
+                // NOTE: 検出し直したらぶつからなくなっていた場合は、そのまま補正する
+                _ if stretches => plan_span_fix(edit, &offbeat_info, &current_timings),
+                _ => plan_fix(
+                    edit,
+                    &offbeat_info,
+                    find_target.moves_whole_object(&offbeat_info.timing_type),
+                    find_target.max_join_gap_frames(),
+                )
+                .map(|moves| (moves, Vec::new())),
+            };
+            // NOTE: 隣を縮めたり押し出したりした先でぶつかるものも、補正できなかったものとして返す
+            let planned = planned.and_then(|(moves, spanned)| {
+                validate_moves(edit, &moves)?;
+                claimed.claim(&moves)?;
+                anyhow::Ok((moves, spanned))
+            });
+            match planned {
+                Ok((moves, spanned)) => {
+                    for object in spanned {
+                        spanned_objects.insert(object, plans.len());
+                    }
+                    plans.push(FixPlan {
+                        index,
+                        offbeat_info,
+                        moves,
+                        covered: Vec::new(),
+                    });
+                }
+                Err(e) => failures.push(FixFailure {
+                    index,
+                    reason: e.to_string(),
+                    outdated: false,
+                }),
+            }
+        }
+        anyhow::Ok((plans, failures))
+    })?
+}
+
+/// 一括補正で、すでに他のズレの補正で動かすことになった区間
+#[derive(Default)]
+struct MovedSections {
+    sections: std::collections::HashSet<(ObjectHandle, usize)>,
+    objects: std::collections::HashSet<ObjectHandle>,
+    whole_objects: std::collections::HashSet<ObjectHandle>,
+}
+
+impl MovedSections {
+    /// 他の補正と同じ区間を動かす場合はエラーにする。
+    /// オブジェクト全体を動かす場合は、そのオブジェクトのどの区間とも重なるとみなす
+    fn claim(&mut self, moves: &[PlannedMove]) -> anyhow::Result<()> {
+        for planned_move in moves {
+            let conflicts = self.whole_objects.contains(&planned_move.object)
+                || if planned_move.whole_object {
+                    self.objects.contains(&planned_move.object)
                 } else {
-                    let position = object.get_layer_frame()?;
-                    let left_position = edit.get_object_layer_frame(*object_handle_left)?;
-                    edit.move_object_section(
-                        *object,
-                        0,
-                        (position.start as i64 - offbeat_info.offset_frames)
-                            .try_into()
-                            .context("fixed frame out of range")?,
-                    )?;
-                    edit.move_object_section(
-                        *object_handle_left,
-                        edit.get_object_section_num(*object_handle_left)?,
-                        (left_position.end as i64 - offbeat_info.offset_frames)
-                            .try_into()
-                            .context("fixed frame out of range")?,
-                    )?;
+                    self.sections
+                        .contains(&(planned_move.object, planned_move.section))
+                };
+            if conflicts {
+                anyhow::bail!("Moves the same object as another fix in this batch");
+            }
+        }
+        for planned_move in moves {
+            self.sections
+                .insert((planned_move.object, planned_move.section));
+            self.objects.insert(planned_move.object);
+            if planned_move.whole_object {
+                self.whole_objects.insert(planned_move.object);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 求めた移動をまとめて適用する。1回の編集で行うので、AviUtl2の「元に戻す」1回で全部戻る
+///
+/// 実際に行った移動は、行った順にappliedに追加する。
+pub fn apply_fix_plans(
+    plans: &[FixPlan],
+    applied: &mut Vec<PlannedMove>,
+) -> anyhow::Result<Vec<FixFailure>> {
+    // NOTE: 左に動かすものは左から、右に動かすものは右から動かすと、
+    //       隣のタイミングがまだ動いていないせいで動かせない、ということが起きにくい
+    let mut order: Vec<&FixPlan> = plans.iter().collect();
+    order.sort_by_key(|plan| {
+        let offbeat_info = &plan.offbeat_info;
+        if offbeat_info.offset_frames > 0 {
+            (0, offbeat_info.frame as i64)
+        } else {
+            (1, -(offbeat_info.frame as i64))
+        }
+    });
+    let failures = crate::EDIT_HANDLE.call_edit_section(|edit| {
+        let mut failures = Vec::new();
+        for plan in order {
+            match apply_moves(edit, &plan.moves) {
+                Ok(()) => applied.extend_from_slice(&plan.moves),
+                Err(e) => failures.push(FixFailure {
+                    index: plan.index,
+                    reason: e.to_string(),
+                    outdated: false,
+                }),
+            }
+        }
+        failures
+    })?;
+    Ok(failures)
+}
+
+/// 補正で行った移動を、逆の順番で元の位置に戻す。戻せなかったものは理由とともに返す
+///
+/// 補正はズレのカードごとに別の編集になるので、検出してから行った補正をプラグイン側で記録しておき、
+/// 1回の編集でまとめて戻す。
+pub fn revert_moves(moves: &[PlannedMove]) -> anyhow::Result<Vec<FixFailure>> {
+    let failures = crate::EDIT_HANDLE.call_edit_section(|edit| {
+        let mut failures = Vec::new();
+        for (index, planned_move) in moves.iter().enumerate().rev() {
+            if let Err(e) = revert_move(edit, planned_move) {
+                failures.push(FixFailure {
+                    index,
+                    reason: e.to_string(),
+                    outdated: false,
+                });
+            }
+        }
+        failures
+    })?;
+    Ok(failures)
+}
+
+fn revert_move(
+    edit: &aviutl2::generic::EditSection,
+    planned_move: &PlannedMove,
+) -> anyhow::Result<()> {
+    if !edit.object_exists(planned_move.object) {
+        anyhow::bail!("Object no longer exists");
+    }
+    let current_frame = section_frame(edit, planned_move.object, planned_move.section)?;
+    // NOTE: 補正の後に手で動かされた区間は、その編集を上書きしないように戻さない
+    if current_frame != planned_move.new_frame {
+        anyhow::bail!(
+            "Section was moved after the fix: expected {}f, found {}f",
+            planned_move.new_frame,
+            current_frame
+        );
+    }
+    move_to(edit, planned_move, planned_move.old_frame)
+}
+
+/// 1つのズレの移動をまとめて行う。途中で失敗したら、同じ編集の中でそれまでの移動を戻す
+fn apply_moves(edit: &aviutl2::generic::EditSection, moves: &[PlannedMove]) -> anyhow::Result<()> {
+    // NOTE: 境界の終点と始点のように組で動かすものは、片方だけ動くと隙間や重なりができるので、
+    //       動かす前にすべての移動を確かめる
+    for planned_move in moves {
+        if !edit.object_exists(planned_move.object) {
+            anyhow::bail!("Object no longer exists");
+        }
+    }
+    validate_moves(edit, moves)?;
+    for (i, planned_move) in moves.iter().enumerate() {
+        if let Err(e) = move_to(edit, planned_move, planned_move.new_frame) {
+            for done in moves[..i].iter().rev() {
+                move_to(edit, done, done.old_frame)?;
+            }
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+fn move_to(
+    edit: &aviutl2::generic::EditSection,
+    planned_move: &PlannedMove,
+    frame: usize,
+) -> anyhow::Result<()> {
+    if planned_move.whole_object {
+        let layer = edit.get_object_layer_frame(planned_move.object)?.layer;
+        edit.move_object(planned_move.object, layer, frame)?;
+    } else {
+        edit.move_object_section(planned_move.object, planned_move.section, frame)?;
+    }
+    Ok(())
+}
+
+/// 移動を順に行ったときに、各移動の前の位置が合っているかと、移動した後に
+/// オブジェクトの区間の順番が入れ替わったり、同じレイヤーの他のオブジェクトと重なったりしないかを確かめる
+fn validate_moves(
+    edit: &aviutl2::generic::ReadSection,
+    moves: &[PlannedMove],
+) -> anyhow::Result<()> {
+    let mut moved_frames: std::collections::HashMap<ObjectHandle, Vec<usize>> =
+        std::collections::HashMap::new();
+    for planned_move in moves {
+        let frames = match moved_frames.entry(planned_move.object) {
+            std::collections::hash_map::Entry::Occupied(entry) => entry.into_mut(),
+            std::collections::hash_map::Entry::Vacant(entry) => {
+                let section_num = edit.get_object_section_num(planned_move.object)?;
+                entry.insert(
+                    (0..=section_num)
+                        .map(|section| section_frame(edit, planned_move.object, section))
+                        .collect::<anyhow::Result<_>>()?,
+                )
+            }
+        };
+        let current_frame = if planned_move.whole_object {
+            frames[0]
+        } else {
+            frames[planned_move.section]
+        };
+        // NOTE: 確認画面を開いている間に動かされた区間は、古い位置からの補正になるので動かさない
+        if current_frame != planned_move.old_frame {
+            anyhow::bail!(
+                "Section was moved after planning: expected {}f, found {}f",
+                planned_move.old_frame,
+                current_frame
+            );
+        }
+        if planned_move.whole_object {
+            let delta = planned_move.new_frame as i64 - planned_move.old_frame as i64;
+            for frame in frames.iter_mut() {
+                *frame = (*frame as i64 + delta)
+                    .try_into()
+                    .context("shifted frame out of range")?;
+            }
+        } else {
+            frames[planned_move.section] = planned_move.new_frame;
+        }
+    }
+    for (object, frames) in &moved_frames {
+        let last = frames.len() - 1;
+        // NOTE: 終了位置は最後の区間の開始位置と同じフレームでもよい
+        for i in 1..=last {
+            if frames[i] < frames[i - 1] || (i < last && frames[i] == frames[i - 1]) {
+                anyhow::bail!("Sections of an object would cross at {}f", frames[i]);
+            }
+        }
+        let (start, end) = (frames[0], frames[last]);
+        let layer = edit.get_object_layer_frame(*object)?.layer;
+        for (position, other) in edit.objects_in_layer(layer) {
+            if other == *object {
+                continue;
+            }
+            let (other_start, other_end) = match moved_frames.get(&other) {
+                Some(other_frames) => (other_frames[0], other_frames[other_frames.len() - 1]),
+                None => (position.start, position.end),
+            };
+            if start <= other_end && other_start <= end {
+                anyhow::bail!(
+                    "Would overlap another object on the same layer at {}f",
+                    start.max(other_start)
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 区間の位置を返す。区間数を渡すと終了位置を返す
+fn section_frame(
+    edit: &aviutl2::generic::ReadSection,
+    object: ObjectHandle,
+    section: usize,
+) -> anyhow::Result<usize> {
+    if section == edit.get_object_section_num(object)? {
+        Ok(edit.get_object_layer_frame(object)?.end)
+    } else {
+        Ok(edit.get_object_section_frame(object, section)?)
+    }
+}
+
+/// whole_objectのときは、後ろにmax_join_gap以下の隙間でつながっているオブジェクトも一緒にずらす
+fn plan_fix(
+    edit: &aviutl2::generic::ReadSection,
+    offbeat_info: &OffbeatInfo,
+    whole_object: bool,
+    max_join_gap: usize,
+) -> anyhow::Result<Vec<PlannedMove>> {
+    if let Some(blocked) = &offbeat_info.blocked {
+        anyhow::bail!("{blocked}");
+    }
+    let planned_move = |object: ObjectHandle, section: usize, old_frame: usize| {
+        let new_frame = (old_frame as i64 - offbeat_info.offset_frames)
+            .try_into()
+            .context("fixed frame out of range")?;
+        anyhow::Ok(PlannedMove {
+            object,
+            section,
+            old_frame,
+            new_frame,
+            whole_object: false,
+        })
+    };
+    let object = offbeat_info.object;
+    let moves = match &offbeat_info.timing_type {
+        TimingType::Start { .. } if whole_object => plan_chain_shift(
+            edit,
+            object,
+            -offbeat_info.offset_frames,
+            None,
+            &offbeat_info.chain_boundaries,
+            max_join_gap,
+        )?,
+        TimingType::Start { .. } => {
+            let position = edit.get_object_layer_frame(object)?;
+            vec![planned_move(object, 0, position.start)?]
+        }
+        TimingType::End { .. } => {
+            let position = edit.get_object_layer_frame(object)?;
+            vec![planned_move(
+                object,
+                edit.get_object_section_num(object)?,
+                position.end,
+            )?]
+        }
+        TimingType::Keyframe { keyframe_index, .. } => {
+            let position = edit.get_object_section_frame(object, *keyframe_index + 1)?;
+            vec![planned_move(object, *keyframe_index + 1, position)?]
+        }
+        TimingType::EndThenStart {
+            object_handle_left, ..
+        } if whole_object => plan_chain_shift(
+            edit,
+            object,
+            -offbeat_info.offset_frames,
+            Some(*object_handle_left),
+            &offbeat_info.chain_boundaries,
+            max_join_gap,
+        )?,
+        TimingType::EndThenStart {
+            object_handle_left, ..
+        } => {
+            let position = edit.get_object_layer_frame(object)?;
+            let left_position = edit.get_object_layer_frame(*object_handle_left)?;
+            let start_move = planned_move(object, 0, position.start)?;
+            // NOTE: 隙間があった場合も、左のオブジェクトの終点は始点の直前に合わせて詰める
+            let end_move = PlannedMove {
+                object: *object_handle_left,
+                section: edit.get_object_section_num(*object_handle_left)?,
+                old_frame: left_position.end,
+                new_frame: start_move
+                    .new_frame
+                    .checked_sub(1)
+                    .context("fixed frame out of range")?,
+                whole_object: false,
+            };
+            order_moves(vec![start_move, end_move])
+        }
+    };
+    Ok(moves)
+}
+
+/// 開始位置と終了位置をまとめてグリッドに合わせ、中間点を新しい長さに合わせて伸縮する移動と、
+/// 伸縮するオブジェクトを求める。
+///
+/// ズレている境界でつながっているオブジェクトは、まとめて伸縮する。
+fn plan_span_fix(
+    edit: &aviutl2::generic::ReadSection,
+    offbeat_info: &OffbeatInfo,
+    current_timings: &std::collections::HashMap<TimingKey, OffbeatInfo>,
+) -> anyhow::Result<(Vec<PlannedMove>, Vec<ObjectHandle>)> {
+    if let Some(blocked) = &offbeat_info.blocked {
+        anyhow::bail!("{blocked}");
+    }
+
+    // NOTE: ぶつかるものは動かさず、その境界はそのままにする
+    let boundary = |key: &TimingKey| {
+        current_timings
+            .get(key)
+            .filter(|timing| timing.blocked.is_none())
+    };
+    let end_boundary = |object: ObjectHandle| {
+        boundary(&(object, usize::MAX)).or_else(|| {
+            current_timings.values().find(|timing| {
+                timing.blocked.is_none()
+                    && matches!(
+                        &timing.timing_type,
+                        TimingType::EndThenStart { object_handle_left, .. }
+                            if *object_handle_left == object
+                    )
+            })
+        })
+    };
+
+    // NOTE: 境界の片側だけを伸縮すると、もう片側の区間を別のプランと取り合うことになるので、
+    //       ズレている境界でつながっているオブジェクトをすべて集める
+    let mut objects = Vec::new();
+    let mut pending = timing_objects(offbeat_info);
+    while let Some(object) = pending.pop() {
+        if objects.contains(&object) {
+            continue;
+        }
+        objects.push(object);
+        if let Some(timing) = boundary(&(object, 0))
+            && let TimingType::EndThenStart {
+                object_handle_left, ..
+            } = &timing.timing_type
+        {
+            pending.push(*object_handle_left);
+        }
+        if let Some(timing) = end_boundary(object)
+            && matches!(timing.timing_type, TimingType::EndThenStart { .. })
+        {
+            pending.push(timing.object);
+        }
+    }
+
+    let mut moves = Vec::new();
+    for &object in &objects {
+        let section_num = edit.get_object_section_num(object)?;
+        let frames = (0..=section_num)
+            .map(|section| section_frame(edit, object, section))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let start = frames[0] as i64;
+        let end = frames[section_num] as i64;
+        let new_start = start - boundary(&(object, 0)).map_or(0, |timing| timing.offset_frames);
+        let new_end = match end_boundary(object) {
+            // NOTE: 隙間のある境界は、終点を次の始点の直前まで詰める
+            Some(timing) if matches!(timing.timing_type, TimingType::EndThenStart { .. }) => {
+                timing.frame as i64 - timing.offset_frames - 1
+            }
+            Some(timing) => end - timing.offset_frames,
+            None => end,
+        };
+        if new_end - new_start < section_num as i64 {
+            anyhow::bail!("Object is too short to keep its keyframes");
+        }
+
+        let new_frames = stretch_sections(&frames, new_start, new_end);
+        for (section, (&old_frame, &new_frame)) in frames.iter().zip(&new_frames).enumerate() {
+            let new_frame = new_frame.try_into().context("fixed frame out of range")?;
+            if new_frame != old_frame {
+                moves.push(PlannedMove {
+                    object,
+                    section,
+                    old_frame,
+                    new_frame,
+                    whole_object: false,
+                });
+            }
+        }
+    }
+    Ok((order_moves(moves), objects))
+}
+
+/// 同じレイヤーの区間の移動を、途中で区間が重ならない順番に並べる
+fn order_moves(moves: Vec<PlannedMove>) -> Vec<PlannedMove> {
+    // NOTE: 左に動かすものは左から、右に動かすものは右から動かすと、
+    //       途中で区間の順番が入れ替わらない
+    let (mut left_moves, mut right_moves): (Vec<_>, Vec<_>) = moves
+        .into_iter()
+        .partition(|planned_move| planned_move.new_frame < planned_move.old_frame);
+    left_moves.sort_by_key(|planned_move| planned_move.old_frame);
+    right_moves.sort_by_key(|planned_move| std::cmp::Reverse(planned_move.old_frame));
+    left_moves.extend(right_moves);
+    left_moves
+}
+
+/// 区間の位置を、開始位置と終了位置が新しい位置になるように比例で伸縮する。
+/// 区間同士が重ならないように、1フレーム以上空ける
+fn stretch_sections(frames: &[usize], new_start: i64, new_end: i64) -> Vec<i64> {
+    let last = frames.len() - 1;
+    let start = frames[0] as f64;
+    let length = (frames[last] as f64 - start).max(1.0);
+    let scale = (new_end - new_start) as f64 / length;
+    let mut new_frames: Vec<i64> = frames
+        .iter()
+        .map(|&frame| new_start + ((frame as f64 - start) * scale).round() as i64)
+        .collect();
+    new_frames[0] = new_start;
+    new_frames[last] = new_end;
+    for i in 1..last {
+        let min_frame = new_frames[i - 1] + 1;
+        let max_frame = new_end - (last - i) as i64;
+        new_frames[i] = new_frames[i].clamp(min_frame, max_frame);
+    }
+    new_frames
+}
+
+fn plan_blocked_fix(
+    edit: &aviutl2::generic::ReadSection,
+    grid: &Grid,
+    context: &FixContext,
+    offbeat_info: &OffbeatInfo,
+    resolution: BlockResolution,
+) -> anyhow::Result<Vec<PlannedMove>> {
+    let whole_object = context
+        .find_target
+        .moves_whole_object(&offbeat_info.timing_type);
+    let max_join_gap = context.find_target.max_join_gap_frames();
+    let Some(blocked) = &offbeat_info.blocked else {
+        return plan_fix(edit, offbeat_info, whole_object, max_join_gap);
+    };
+    let Blocked::Neighbour {
+        side,
+        neighbour,
+        opposite_frame,
+    } = blocked
+    else {
+        anyhow::bail!("{blocked}");
+    };
+    // NOTE: オブジェクトごと動かすときは、後ろにつながっているオブジェクトの終点が
+    //       後ろの隣とぶつからないようにする
+    let span = if whole_object {
+        let (last_position, _) = *chain_objects(edit, offbeat_info.object, max_join_gap)?
+            .last()
+            .unwrap();
+        (last_position.end - offbeat_info.frame) as i64
+    } else {
+        0
+    };
+    let unblocked = |offset_frames: i64| OffbeatInfo {
+        offset_frames,
+        blocked: None,
+        ..offbeat_info.clone()
+    };
+    let target_frame = offbeat_info.frame as i64 - offbeat_info.offset_frames;
+    // NOTE: 境界は左のオブジェクトの終点が1フレーム前に来る（detect_offsyncと同じ）
+    let lead = if matches!(offbeat_info.timing_type, TimingType::EndThenStart { .. }) {
+        1
+    } else {
+        0
+    };
+    // NOTE: 隣のタイミングは、補正後の位置と1フレーム以上空ける
+    let neighbour_frame = match side {
+        NeighbourSide::Previous => target_frame - lead - 1,
+        NeighbourSide::Next => target_frame + span + 1,
+    };
+    match resolution {
+        BlockResolution::NextGridLine if context.find_target.length_note.is_some() => {
+            anyhow::bail!("Snapping to a grid line is not available when checking lengths");
+        }
+        BlockResolution::NextGridLine => {
+            // NOTE: 終端は次のフレームがグリッドに触れるようにする（detect_offsyncと同じ）
+            let edge = if matches!(offbeat_info.timing_type, TimingType::End { .. }) {
+                1
+            } else {
+                0
+            };
+            let (mut search_frame, direction, step) = match side {
+                NeighbourSide::Previous => (
+                    neighbour.frame as i64 + 1 + lead + edge,
+                    crate::grid::SnapDirection::Later,
+                    1,
+                ),
+                NeighbourSide::Next => (
+                    neighbour.frame as i64 - 1 - span + edge,
+                    crate::grid::SnapDirection::Earlier,
+                    -1,
+                ),
+            };
+            let limit_frame = search_frame;
+            let info = crate::EDIT_HANDLE.get_edit_info();
+            // NOTE: 丸めで隣の側に戻ってしまうことがあるので、隣から離れるまで探す
+            let grid_frame = loop {
+                let Some(grid_frame) = grid.nearest_frame(
+                    &info,
+                    &context.grid_config,
+                    search_frame as f64,
+                    direction,
+                )?
+                else {
+                    anyhow::bail!("No grid line found beyond the neighbouring timing");
+                };
+                let grid_frame = grid_frame as i64;
+                if (grid_frame - limit_frame) * step >= 0 {
+                    break grid_frame;
+                }
+                search_frame += step;
+            };
+            let new_frame = grid_frame - edge;
+            let reaches_opposite = opposite_frame.is_some_and(|opposite_frame| match side {
+                NeighbourSide::Previous => new_frame + span >= opposite_frame as i64,
+                NeighbourSide::Next => new_frame <= opposite_frame as i64,
+            });
+            if reaches_opposite {
+                anyhow::bail!("No grid line between the neighbouring timings");
+            }
+            plan_fix(
+                edit,
+                &unblocked(offbeat_info.frame as i64 - new_frame),
+                whole_object,
+                max_join_gap,
+            )
+        }
+        BlockResolution::ShortenNeighbour => {
+            let joined_to_self = neighbour.object == offbeat_info.object
+                || matches!(
+                    &neighbour.timing_type,
+                    TimingType::EndThenStart { object_handle_left, .. }
+                        if *object_handle_left == offbeat_info.object
+                );
+            if whole_object && joined_to_self {
+                anyhow::bail!("Neighbouring timing moves together with this object");
+            }
+            // NOTE: 長さを見ているときは、同じオブジェクトの開始位置や中間点を動かすと、
+            //       揃えようとしている長さや中身が変わる
+            if context.find_target.length_note.is_some() && neighbour.object == offbeat_info.object
+            {
+                anyhow::bail!("The object's own section cannot be shortened when checking lengths");
+            }
+            let neighbour_info = OffbeatInfo {
+                offset_frames: neighbour.frame as i64 - neighbour_frame,
+                ..neighbour.as_ref().clone()
+            };
+            let mut moves = plan_fix(edit, &neighbour_info, false, max_join_gap)?;
+            moves.extend(plan_fix(
+                edit,
+                &unblocked(offbeat_info.offset_frames),
+                whole_object,
+                max_join_gap,
+            )?);
+            Ok(moves)
+        }
+        BlockResolution::PushNeighbour => {
+            let pushable = match (side, &neighbour.timing_type) {
+                (NeighbourSide::Previous, TimingType::End { .. })
+                | (NeighbourSide::Next, TimingType::Start { .. }) => {
+                    neighbour.object != offbeat_info.object
                 }
+                _ => false,
+            };
+            if !pushable {
+                anyhow::bail!("Only a separate neighbouring object can be pushed");
             }
+            let mut moves = plan_object_shift(
+                edit,
+                neighbour.object,
+                neighbour_frame - neighbour.frame as i64,
+            )?;
+            moves.extend(plan_fix(
+                edit,
+                &unblocked(offbeat_info.offset_frames),
+                whole_object,
+                max_join_gap,
+            )?);
+            Ok(moves)
+        }
+    }
+}
+
+/// オブジェクトと、同じレイヤーでその後ろにmax_join_gap以下の隙間でつながっているオブジェクト
+fn chain_objects(
+    edit: &aviutl2::generic::ReadSection,
+    object: ObjectHandle,
+    max_join_gap: usize,
+) -> anyhow::Result<Vec<(aviutl2::generic::ObjectLayerFrame, ObjectHandle)>> {
+    let position = edit.get_object_layer_frame(object)?;
+    let mut followers: Vec<_> = edit
+        .objects_in_layer(position.layer)
+        .filter(|(other_position, _)| other_position.start > position.start)
+        .collect();
+    followers.sort_by_key(|(other_position, _)| other_position.start);
+    let mut chain = vec![(position, object)];
+    for (other_position, other) in followers {
+        let last_end = chain.last().unwrap().0.end;
+        if other_position.start - last_end - 1 > max_join_gap {
+            break;
         }
+        chain.push((other_position, other));
+    }
+    Ok(chain)
+}
 
+/// オブジェクトを、後ろにつながっているオブジェクトと一緒に、長さと間隔を変えずにずらす移動。
+///
+/// leftを渡すと、そのオブジェクトの終点をずらした始点の直前に合わせる。
+/// chain_boundariesの境界もグリッドに合わせ、そこから後ろはその境界の補正の分だけずらす
+// NOTE: 1つだけずらすと後ろの境界に隙間や重なりができるので、つながっている残りもまとめてずらす
+fn plan_chain_shift(
+    edit: &aviutl2::generic::ReadSection,
+    object: ObjectHandle,
+    delta: i64,
+    left: Option<ObjectHandle>,
+    chain_boundaries: &[OffbeatInfo],
+    max_join_gap: usize,
+) -> anyhow::Result<Vec<PlannedMove>> {
+    // （左のオブジェクトの位置, 左のオブジェクト, 左のオブジェクトをずらす量）
+    let mut left = match left {
+        Some(left) => Some((edit.get_object_layer_frame(left)?, left, 0)),
+        None => None,
+    };
+    let mut delta = delta;
+    let mut moves = Vec::new();
+    for (index, (position, object)) in chain_objects(edit, object, max_join_gap)?
+        .into_iter()
+        .enumerate()
+    {
+        let boundary = chain_boundaries
+            .iter()
+            .find(|boundary| boundary.object == object);
+        if let Some(boundary) = boundary
+            && index > 0
+        {
+            delta = boundary.frame as i64 - boundary.offset_frames - position.start as i64;
+        }
+        if (index == 0 || boundary.is_some())
+            && let Some((left_position, left_object, left_delta)) = left
+        {
+            // NOTE: 左のオブジェクトもずらす場合、終点は左にずらすときはずらした後に、
+            //       右にずらすときはずらす前に動かす（order_keyed_movesでその順番に並ぶ）ので、
+            //       そのときの位置で表す
+            let new_end = position.start as i64 + delta - 1 - left_delta.max(0);
+            moves.push((
+                PlannedMove {
+                    object: left_object,
+                    section: edit.get_object_section_num(left_object)?,
+                    old_frame: (left_position.end as i64 + left_delta.min(0))
+                        .try_into()
+                        .context("shifted frame out of range")?,
+                    new_frame: new_end.try_into().context("fixed frame out of range")?,
+                    whole_object: false,
+                },
+                (left_position.start, 1),
+            ));
+        }
+        for planned_move in plan_object_shift(edit, object, delta)? {
+            moves.push((planned_move, (position.start, 0)));
+        }
+        left = Some((position, object, delta));
+    }
+    Ok(order_keyed_moves(moves))
+}
+
+/// 同じレイヤーのオブジェクトのずらしと区間の移動を、途中で区間が重ならない順番に並べる。
+///
+/// キーは動かすオブジェクトの元の開始位置と、同じオブジェクトの中での順番（ずらしが0、区間の移動が1）
+fn order_keyed_moves(moves: Vec<(PlannedMove, (usize, u8))>) -> Vec<PlannedMove> {
+    // NOTE: order_movesと同じく左に動かすものは左から、右に動かすものは右から動かす。
+    //       同じオブジェクトの中では、左に動かすときはずらしてから、右に動かすときは先に区間を動かす
+    let (mut left_moves, mut right_moves): (Vec<_>, Vec<_>) = moves
+        .into_iter()
+        .partition(|(planned_move, _)| planned_move.new_frame < planned_move.old_frame);
+    left_moves.sort_by_key(|(_, key)| *key);
+    right_moves.sort_by_key(|(_, key)| std::cmp::Reverse(*key));
+    left_moves
+        .into_iter()
+        .chain(right_moves)
+        .map(|(planned_move, _)| planned_move)
+        .collect()
+}
+
+/// オブジェクトを、長さと中間点の間隔を変えずにずらす移動
+fn plan_object_shift(
+    edit: &aviutl2::generic::ReadSection,
+    object: ObjectHandle,
+    delta: i64,
+) -> anyhow::Result<Vec<PlannedMove>> {
+    let old_frame = edit.get_object_layer_frame(object)?.start;
+    let new_frame = (old_frame as i64 + delta)
+        .try_into()
+        .context("shifted frame out of range")?;
+    Ok(vec![PlannedMove {
+        object,
+        section: 0,
+        old_frame,
+        new_frame,
+        whole_object: true,
+    }])
+}
+
+/// オブジェクトの境界（始点・中間点・終点）からBPMグリッドを推定する
+pub fn estimate_bpm_grid(
+    grid_config: &crate::grid::GridConfig,
+) -> anyhow::Result<crate::grid::BpmEstimate> {
+    crate::EDIT_HANDLE.call_read_section(|edit| {
+        let info = crate::EDIT_HANDLE.get_edit_info();
+        let mut frames = Vec::new();
+        for timing in collect_timings(edit, 0..=info.layer_max)? {
+            if edit.count_object_effect(timing.object, crate::marker::IGNORE_MARKER_NAME)? > 0 {
+                continue;
+            }
+            // NOTE: 終端は次のフレームがグリッドに触れるようにする（find_offsync_objectsと同じ）
+            let frame = match timing.timing_type {
+                TimingType::End { .. } => timing.frame + 1,
+                _ => timing.frame,
+            };
+            frames.push(frame as f64);
+        }
+        // NOTE: 隣接するオブジェクトの終点と始点は同じ境界なので、重複を除く
+        frames.sort_by(f64::total_cmp);
+        frames.dedup();
+        crate::grid::estimate_bpm(&info, &frames, grid_config.rounding)
+    })?
+}
+
+/// フォーカスしているオブジェクトの開始フレーム
+pub fn focused_object_start() -> anyhow::Result<Option<usize>> {
+    crate::EDIT_HANDLE.call_read_section(|edit| {
+        let Some(object) = edit.get_focused_object()? else {
+            return anyhow::Ok(None);
+        };
+        anyhow::Ok(Some(edit.get_object_layer_frame(object)?.start))
+    })?
+}
+
+/// プロジェクトのBPMグリッドを読む
+pub fn read_grid_bpm_list() -> anyhow::Result<Vec<aviutl2::generic::BpmInfo>> {
+    crate::EDIT_HANDLE.call_read_section(|edit| anyhow::Ok(edit.get_grid_bpm_list()?))?
+}
+
+pub fn write_grid_bpm_list(bpm_list: &[aviutl2::generic::BpmInfo]) -> anyhow::Result<()> {
+    if bpm_list.is_empty() {
+        anyhow::bail!("BPM grid is empty");
+    }
+    crate::EDIT_HANDLE.call_edit_section(|edit| {
+        edit.set_grid_bpm_list(bpm_list)?;
         anyhow::Ok(())
     })??;
     Ok(())
@@ -323,3 +2145,22 @@ pub fn mark_ignored(objects: &[ObjectHandle]) -> anyhow::Result<()> {
     })??;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stretch_sections_scales_keyframes_proportionally() {
+        assert_eq!(
+            stretch_sections(&[10, 20, 30, 50], 0, 80),
+            vec![0, 20, 40, 80]
+        );
+    }
+
+    #[test]
+    fn stretch_sections_keeps_sections_apart() {
+        assert_eq!(stretch_sections(&[0, 1, 2, 100], 0, 10), vec![0, 1, 2, 10]);
+        assert_eq!(stretch_sections(&[0, 98, 99, 100], 0, 3), vec![0, 1, 2, 3]);
+    }
+}
@@ -26,31 +26,313 @@ use aviutl2::anyhow::{self, Context};
 // SOFTWARE.
 // ```
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct GridConfig {
+    /// 1拍あたりの分割数（1 = 1拍、2 = 1/2拍、3 = 3連符...）
+    ///
+    /// 複数指定した場合は、その中で一番近いグリッドにスナップする。
+    pub subdivisions: Vec<u32>,
+    /// 2つの分割ごとに、裏の分割をどこに置くか（0.5 = ストレート、2/3 = 3連符のスウィング）
+    pub swing: f64,
+    /// 小節線（BpmInfo.beat拍ごと）にのみスナップする
+    pub snap_to_bar: bool,
+    /// BPM情報の間のテンポの変化のしかた
+    pub tempo_ramp: TempoRamp,
+    /// グリッドの位置をフレームに丸める方法
+    pub rounding: FrameRounding,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FrameRounding {
+    /// 拍の瞬間以降の最初のフレーム
+    #[default]
+    Ceil,
+    /// 拍の瞬間に一番近いフレーム
+    Round,
+    /// 拍の瞬間を含むフレーム
+    Floor,
+}
+
+impl FrameRounding {
+    pub fn apply(self, frame_num: f64) -> f64 {
+        // NOTE: 計算誤差でちょうどのフレームの手前に来たときに、1つ前のフレームにならないようにする
+        match self {
+            FrameRounding::Ceil => frame_num.ceil(),
+            FrameRounding::Round => frame_num.round(),
+            FrameRounding::Floor => (frame_num + 1e-9).floor(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TempoRamp {
+    /// 次のBPM情報で一気に切り替わる
+    #[default]
+    None,
+    /// 次のBPM情報まで時間に対して直線的に変化する
+    Linear,
+    /// 次のBPM情報まで時間に対して指数的に変化する
+    Exponential,
+}
+
+/// どちら向きのグリッドにスナップするか
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SnapDirection {
+    /// 一番近いグリッドにスナップする
+    #[default]
+    Nearest,
+    /// 前のグリッドにのみスナップする（後ろには動かさない）
+    Earlier,
+    /// 後ろのグリッドにのみスナップする（前には動かさない）
+    Later,
+}
+
+impl SnapDirection {
+    fn allows(self, candidate: f64, frame_num: f64) -> bool {
+        match self {
+            SnapDirection::Nearest => true,
+            SnapDirection::Earlier => candidate <= frame_num,
+            SnapDirection::Later => candidate >= frame_num,
+        }
+    }
+
+    /// 2つの制約を両方満たす向きを返す。両立しない場合はNone
+    pub fn intersect(self, other: SnapDirection) -> Option<SnapDirection> {
+        match (self, other) {
+            (SnapDirection::Nearest, direction) | (direction, SnapDirection::Nearest) => {
+                Some(direction)
+            }
+            (left, right) if left == right => Some(left),
+            _ => None,
+        }
+    }
+}
+
+impl GridConfig {
+    /// グリッドの間隔の最小値（拍）
+    pub fn min_beat_interval(&self, bpm_list: &[aviutl2::generic::BpmInfo]) -> f64 {
+        if self.snap_to_bar {
+            return bpm_list
+                .iter()
+                .map(|bpm| bpm.beat.max(1) as f64)
+                .min_by(f64::total_cmp)
+                .unwrap_or(1.0);
+        }
+        let swing = self.swing.clamp(0.0, 1.0);
+        self.subdivisions
+            .iter()
+            .map(|&subdivision| {
+                let subdivision = subdivision.max(1);
+                if swings(subdivision) {
+                    2.0 / subdivision as f64 * swing.min(1.0 - swing)
+                } else {
+                    1.0 / subdivision as f64
+                }
+            })
+            .min_by(f64::total_cmp)
+            .unwrap_or(1.0)
+    }
+}
+
+/// スウィングをかける分割かどうか
+// NOTE: 2分割を1組にするので、奇数の分割では組が拍をまたいでしまう
+fn swings(subdivision: u32) -> bool {
+    subdivision.is_multiple_of(2)
+}
+
+impl Default for GridConfig {
+    fn default() -> Self {
+        Self {
+            subdivisions: vec![1],
+            swing: 0.5,
+            snap_to_bar: false,
+            tempo_ramp: TempoRamp::None,
+            rounding: FrameRounding::Ceil,
+        }
+    }
+}
+
+struct TempoRampCalc {
+    kind: TempoRamp,
+    start: f64,
+    start_beat: f64,
+    start_tempo: f64,
+    end_tempo: f64,
+    duration: f64,
+}
+
+impl TempoRampCalc {
+    // NOTE: テンポ×秒の積分（= 拍数×60）で計算する
+    fn tempo_integral(&self, elapsed: f64) -> f64 {
+        let (t0, t1, d) = (self.start_tempo, self.end_tempo, self.duration);
+        match self.kind {
+            TempoRamp::None => t0 * elapsed,
+            TempoRamp::Linear => t0 * elapsed + (t1 - t0) * elapsed * elapsed / (2.0 * d),
+            TempoRamp::Exponential => {
+                let log_ratio = (t1 / t0).ln();
+                if log_ratio.abs() < 1e-9 {
+                    t0 * elapsed
+                } else {
+                    t0 * d * ((log_ratio * elapsed / d).exp() - 1.0) / log_ratio
+                }
+            }
+        }
+    }
+
+    fn inverse_tempo_integral(&self, integral: f64) -> f64 {
+        let (t0, t1, d) = (self.start_tempo, self.end_tempo, self.duration);
+        match self.kind {
+            TempoRamp::None => integral / t0,
+            TempoRamp::Linear => {
+                let a = (t1 - t0) / (2.0 * d);
+                2.0 * integral / (t0 + (t0 * t0 + 4.0 * a * integral).max(0.0).sqrt())
+            }
+            TempoRamp::Exponential => {
+                let log_ratio = (t1 / t0).ln();
+                if log_ratio.abs() < 1e-9 {
+                    integral / t0
+                } else {
+                    d * (1.0 + integral * log_ratio / (t0 * d)).ln() / log_ratio
+                }
+            }
+        }
+    }
+
+    fn seconds_to_beat(&self, seconds: f64) -> f64 {
+        let elapsed = seconds - self.start;
+        let ramp_elapsed = elapsed.min(self.duration);
+        let integral =
+            self.tempo_integral(ramp_elapsed) + (elapsed - ramp_elapsed) * self.end_tempo;
+        self.start_beat + integral / 60.0
+    }
+
+    fn beat_to_seconds(&self, beat_num: f64) -> f64 {
+        let integral = (beat_num - self.start_beat) * 60.0;
+        let ramp_integral = self.tempo_integral(self.duration);
+        if integral > ramp_integral {
+            return self.start + self.duration + (integral - ramp_integral) / self.end_tempo;
+        }
+        self.start + self.inverse_tempo_integral(integral)
+    }
+}
+
 struct BpmGridCalc {
     tempo: f64,
     offset: f64,
     rate: f64,
     scale: f64,
+    subdivisions: Vec<u32>,
+    swing: f64,
+    beats_per_bar: Option<f64>,
+    ramp: Option<TempoRampCalc>,
 }
 
 impl BpmGridCalc {
-    fn new(bpm: aviutl2::generic::BpmInfo, rate: f64, scale: f64) -> Self {
+    fn new(
+        bpm: aviutl2::generic::BpmInfo,
+        next_bpm: Option<&aviutl2::generic::BpmInfo>,
+        rate: f64,
+        scale: f64,
+        config: &GridConfig,
+    ) -> Self {
+        let tempo = bpm.tempo as f64;
+        let ramp = next_bpm
+            .filter(|next_bpm| {
+                config.tempo_ramp != TempoRamp::None
+                    && next_bpm.start > bpm.start
+                    && next_bpm.tempo > 0.0
+            })
+            .map(|next_bpm| TempoRampCalc {
+                kind: config.tempo_ramp,
+                start: bpm.start,
+                start_beat: -tempo * bpm.offset as f64 / 60.0,
+                start_tempo: tempo,
+                end_tempo: next_bpm.tempo as f64,
+                duration: next_bpm.start - bpm.start,
+            });
         Self {
-            tempo: bpm.tempo as f64,
+            tempo,
             offset: bpm.start + bpm.offset as f64,
             rate,
             scale,
+            subdivisions: if config.subdivisions.is_empty() {
+                vec![1]
+            } else {
+                config
+                    .subdivisions
+                    .iter()
+                    .map(|&subdivision| subdivision.max(1))
+                    .collect()
+            },
+            swing: config.swing.clamp(0.0, 1.0),
+            beats_per_bar: config.snap_to_bar.then(|| bpm.beat.max(1) as f64),
+            ramp,
+        }
+    }
+
+    /// オフセットだけを変える。テンポの変化の計算はそのまま使う
+    fn set_offset(&mut self, start: f64, offset: f64) {
+        self.offset = start + offset;
+        if let Some(ramp) = &mut self.ramp {
+            ramp.start_beat = -self.tempo * offset / 60.0;
         }
     }
 
     fn beat_to_frame(&self, beat_num: f64) -> f64 {
+        if let Some(ramp) = &self.ramp
+            && beat_num > ramp.start_beat
+        {
+            return ramp.beat_to_seconds(beat_num) * self.rate / self.scale;
+        }
         (60.0 * beat_num + self.tempo * self.offset) * self.rate / (self.tempo * self.scale)
     }
 
     fn frame_to_beat(&self, frame_num: f64) -> f64 {
+        if let Some(ramp) = &self.ramp {
+            let seconds = frame_num * self.scale / self.rate;
+            if seconds > ramp.start {
+                return ramp.seconds_to_beat(seconds);
+            }
+        }
         (self.tempo * self.scale * frame_num - self.tempo * self.rate * self.offset)
             / (60.0 * self.rate)
     }
+
+    fn grid_beats_around(&self, beat_num: f64) -> Vec<f64> {
+        if let Some(beats_per_bar) = self.beats_per_bar {
+            let bar = beat_num / beats_per_bar;
+            return vec![bar.floor() * beats_per_bar, bar.ceil() * beats_per_bar];
+        }
+        self.subdivisions
+            .iter()
+            .flat_map(|&subdivision| {
+                // NOTE: 2分割を1組として、表はそのまま、裏はswingの位置に置く
+                let swing = if swings(subdivision) { self.swing } else { 0.5 };
+                let pair_length = 2.0 / subdivision as f64;
+                let pair_start = (beat_num / pair_length).floor() * pair_length;
+                [
+                    pair_start,
+                    pair_start + pair_length * swing,
+                    pair_start + pair_length,
+                ]
+            })
+            .collect()
+    }
+}
+
+fn sorted_bpm_list(
+    bpm_list: &[aviutl2::generic::BpmInfo],
+) -> anyhow::Result<Vec<aviutl2::generic::BpmInfo>> {
+    if bpm_list.is_empty() {
+        anyhow::bail!("BPM grid is empty");
+    }
+    if let Some(bpm) = bpm_list.iter().find(|bpm| bpm.tempo <= 0.0) {
+        anyhow::bail!("BPM tempo must be positive: {}", bpm.tempo);
+    }
+
+    let mut bpm_list = bpm_list.to_vec();
+    bpm_list.sort_by(|left, right| left.start.total_cmp(&right.start));
+    Ok(bpm_list)
 }
 
 pub fn max_frames_per_beat(
@@ -73,67 +355,808 @@ pub fn max_frames_per_beat(
     Ok(max_seconds_per_beat * fps)
 }
 
+/// 指定したフレームでの1拍の長さ（フレーム）。テンポが変化している場合はその位置での長さ
+pub fn frames_per_beat_at(
+    info: &aviutl2::generic::EditInfo,
+    bpm_list: &[aviutl2::generic::BpmInfo],
+    config: &GridConfig,
+    frame_num: f64,
+) -> anyhow::Result<f64> {
+    frames_per_beat_at_rate(
+        bpm_list,
+        config,
+        frame_num,
+        *info.fps.numer() as f64,
+        *info.fps.denom() as f64,
+    )
+}
+
+fn frames_per_beat_at_rate(
+    bpm_list: &[aviutl2::generic::BpmInfo],
+    config: &GridConfig,
+    frame_num: f64,
+    rate: f64,
+    scale: f64,
+) -> anyhow::Result<f64> {
+    let bpm_list = sorted_bpm_list(bpm_list)?;
+    let index = bpm_list
+        .iter()
+        .rposition(|bpm| bpm.start * rate / scale <= frame_num)
+        .unwrap_or(0);
+    let calc = BpmGridCalc::new(
+        bpm_list[index],
+        bpm_list.get(index + 1),
+        rate,
+        scale,
+        config,
+    );
+    let beat = calc.frame_to_beat(frame_num);
+    Ok(calc.beat_to_frame(beat + 0.5) - calc.beat_to_frame(beat - 0.5))
+}
+
+/// 指定した向きで一番近いグリッドのフレームを返す。その向きにグリッドがなければNone
 pub fn nearest_grid_frame(
     info: &aviutl2::generic::EditInfo,
     bpm_list: &[aviutl2::generic::BpmInfo],
+    config: &GridConfig,
     frame_num: f64,
-) -> anyhow::Result<i32> {
+    direction: SnapDirection,
+) -> anyhow::Result<Option<i32>> {
     nearest_grid_frame_at_rate(
         bpm_list,
+        config,
         frame_num,
         *info.fps.numer() as f64,
         *info.fps.denom() as f64,
+        direction,
     )
 }
 
 fn nearest_grid_frame_at_rate(
     bpm_list: &[aviutl2::generic::BpmInfo],
+    config: &GridConfig,
     frame_num: f64,
     rate: f64,
     scale: f64,
-) -> anyhow::Result<i32> {
-    if bpm_list.is_empty() {
-        anyhow::bail!("BPM grid is empty");
+    direction: SnapDirection,
+) -> anyhow::Result<Option<i32>> {
+    let segments = BpmGridSegments::new_at_rate(bpm_list, config, rate, scale)?;
+    Ok(segments
+        .nearest_frame(frame_num, direction, None)
+        .map(|nearest| nearest.frame()))
+}
+
+/// BPM情報の区間ごとのグリッドの計算。オフセットだけを変えながら何度もグリッドを探すときに、
+/// テンポの変化の計算を作り直さないように使う
+pub struct BpmGridSegments {
+    segments: Vec<BpmGridSegment>,
+    rounding: FrameRounding,
+}
+
+struct BpmGridSegment {
+    calc: BpmGridCalc,
+    start: f64,
+    start_frame: f64,
+    end_frame: Option<f64>,
+}
+
+/// 区間の中で一番近いグリッドのフレーム
+#[derive(Debug, Clone, Copy)]
+pub struct SegmentFrame {
+    index: usize,
+    frame: f64,
+    distance: f64,
+}
+
+impl SegmentFrame {
+    pub fn frame(&self) -> i32 {
+        self.frame as i32
     }
-    if let Some(bpm) = bpm_list.iter().find(|bpm| bpm.tempo <= 0.0) {
-        anyhow::bail!("BPM tempo must be positive: {}", bpm.tempo);
+
+    /// 近いほうを返す。距離が同じなら前の区間のものを選ぶ
+    pub fn nearer(left: Option<Self>, right: Option<Self>) -> Option<Self> {
+        match (left, right) {
+            (Some(left), Some(right)) => {
+                if (right.distance, right.index) < (left.distance, left.index) {
+                    Some(right)
+                } else {
+                    Some(left)
+                }
+            }
+            (left, right) => left.or(right),
+        }
     }
+}
 
-    let mut bpm_list = bpm_list.to_vec();
-    bpm_list.sort_by(|left, right| left.start.total_cmp(&right.start));
+impl BpmGridSegments {
+    /// 区間の順番は、bpm_listを開始位置で並べ替えた順になる
+    pub fn new(
+        info: &aviutl2::generic::EditInfo,
+        bpm_list: &[aviutl2::generic::BpmInfo],
+        config: &GridConfig,
+    ) -> anyhow::Result<Self> {
+        Self::new_at_rate(
+            bpm_list,
+            config,
+            *info.fps.numer() as f64,
+            *info.fps.denom() as f64,
+        )
+    }
 
-    let mut nearest_frame = None;
-    for (index, bpm) in bpm_list.iter().enumerate() {
-        let start_frame = (bpm.start * rate / scale).ceil();
-        let end_frame = bpm_list
-            .get(index + 1)
-            .map(|next| (next.start * rate / scale).ceil());
-        let bpm_calc = BpmGridCalc::new(*bpm, rate, scale);
-        let current_beat = bpm_calc.frame_to_beat(frame_num);
-        for beat in [current_beat.floor(), current_beat.ceil()] {
-            let candidate = bpm_calc.beat_to_frame(beat).ceil();
-            if candidate < start_frame {
+    fn new_at_rate(
+        bpm_list: &[aviutl2::generic::BpmInfo],
+        config: &GridConfig,
+        rate: f64,
+        scale: f64,
+    ) -> anyhow::Result<Self> {
+        let bpm_list = sorted_bpm_list(bpm_list)?;
+        let segments = bpm_list
+            .iter()
+            .enumerate()
+            .map(|(index, bpm)| BpmGridSegment {
+                calc: BpmGridCalc::new(*bpm, bpm_list.get(index + 1), rate, scale, config),
+                start: bpm.start,
+                start_frame: config.rounding.apply(bpm.start * rate / scale),
+                end_frame: bpm_list
+                    .get(index + 1)
+                    .map(|next| config.rounding.apply(next.start * rate / scale)),
+            })
+            .collect();
+        Ok(Self {
+            segments,
+            rounding: config.rounding,
+        })
+    }
+
+    /// 区間の範囲（フレーム）。最後の区間の終わりはNone
+    pub fn frame_range(&self, index: usize) -> (f64, Option<f64>) {
+        let segment = &self.segments[index];
+        (segment.start_frame, segment.end_frame)
+    }
+
+    pub fn set_offset(&mut self, index: usize, offset: f32) {
+        let segment = &mut self.segments[index];
+        segment.calc.set_offset(segment.start, offset as f64);
+    }
+
+    /// 指定した区間の中で、指定した向きで一番近いグリッドのフレーム
+    pub fn nearest_in_segment(
+        &self,
+        index: usize,
+        frame_num: f64,
+        direction: SnapDirection,
+    ) -> Option<SegmentFrame> {
+        let segment = &self.segments[index];
+        let current_beat = segment.calc.frame_to_beat(frame_num);
+        let mut nearest = None;
+        for beat in segment.calc.grid_beats_around(current_beat) {
+            let candidate = self.rounding.apply(segment.calc.beat_to_frame(beat));
+            if candidate < segment.start_frame {
                 continue;
             }
-            if let Some(end_frame) = end_frame
+            if let Some(end_frame) = segment.end_frame
                 && candidate >= end_frame
             {
                 continue;
             }
-            let distance = (candidate - frame_num).abs();
-            nearest_frame = Some(match nearest_frame {
-                Some((nearest, nearest_distance)) if nearest_distance <= distance => {
-                    (nearest, nearest_distance)
+            if !direction.allows(candidate, frame_num) {
+                continue;
+            }
+            nearest = SegmentFrame::nearer(
+                nearest,
+                Some(SegmentFrame {
+                    index,
+                    frame: candidate,
+                    distance: (candidate - frame_num).abs(),
+                }),
+            );
+        }
+        nearest
+    }
+
+    /// すべての区間の中で、指定した向きで一番近いグリッドのフレーム。excludedの区間は除く
+    pub fn nearest_frame(
+        &self,
+        frame_num: f64,
+        direction: SnapDirection,
+        excluded: Option<usize>,
+    ) -> Option<SegmentFrame> {
+        (0..self.segments.len())
+            .filter(|&index| Some(index) != excluded)
+            .fold(None, |nearest, index| {
+                SegmentFrame::nearer(
+                    nearest,
+                    self.nearest_in_segment(index, frame_num, direction),
+                )
+            })
+    }
+}
+
+/// 指定した向きで一番近いオンセットのフレームを返す。その向きにオンセットがなければNone
+pub fn nearest_onset_frame(
+    info: &aviutl2::generic::EditInfo,
+    onsets: &[f64],
+    frame_num: f64,
+    rounding: FrameRounding,
+    direction: SnapDirection,
+) -> Option<i32> {
+    nearest_onset_frame_at_rate(
+        onsets,
+        frame_num,
+        *info.fps.numer() as f64,
+        *info.fps.denom() as f64,
+        rounding,
+        direction,
+    )
+}
+
+fn nearest_onset_frame_at_rate(
+    onsets: &[f64],
+    frame_num: f64,
+    rate: f64,
+    scale: f64,
+    rounding: FrameRounding,
+    direction: SnapDirection,
+) -> Option<i32> {
+    let seconds = frame_num * scale / rate;
+    let index = onsets.partition_point(|&onset| onset < seconds);
+    let nearest = [index.checked_sub(1), Some(index)]
+        .into_iter()
+        .flatten()
+        .filter_map(|index| onsets.get(index))
+        .map(|&onset| rounding.apply(onset * rate / scale))
+        .filter(|&candidate| direction.allows(candidate, frame_num))
+        .min_by(|left, right| {
+            (left - frame_num)
+                .abs()
+                .total_cmp(&(right - frame_num).abs())
+        })?;
+    Some(nearest as i32)
+}
+
+/// 指定したフレームを挟む2つのオンセットの間隔（フレーム）。
+/// 最初のオンセットより前と最後のオンセットより後では、間隔の中央値を使う
+pub fn onset_interval_frames_at(
+    info: &aviutl2::generic::EditInfo,
+    onsets: &[f64],
+    frame_num: f64,
+) -> anyhow::Result<f64> {
+    onset_interval_frames_at_rate(
+        onsets,
+        frame_num,
+        *info.fps.numer() as f64,
+        *info.fps.denom() as f64,
+    )
+}
+
+fn onset_interval_frames_at_rate(
+    onsets: &[f64],
+    frame_num: f64,
+    rate: f64,
+    scale: f64,
+) -> anyhow::Result<f64> {
+    if onsets.len() < 2 {
+        anyhow::bail!("At least two onsets are required");
+    }
+    let seconds = frame_num * scale / rate;
+    let index = onsets.partition_point(|&onset| onset < seconds);
+    let interval = if index == 0 || index == onsets.len() {
+        let mut intervals: Vec<f64> = onsets
+            .windows(2)
+            .map(|window| window[1] - window[0])
+            .collect();
+        intervals.sort_by(f64::total_cmp);
+        intervals[intervals.len() / 2]
+    } else {
+        onsets[index] - onsets[index - 1]
+    };
+    Ok(interval * rate / scale)
+}
+
+pub fn max_onset_interval_frames(
+    info: &aviutl2::generic::EditInfo,
+    onsets: &[f64],
+) -> anyhow::Result<f64> {
+    let fps = *info.fps.numer() as f64 / *info.fps.denom() as f64;
+    let max_interval = onsets
+        .windows(2)
+        .map(|window| window[1] - window[0])
+        .max_by(f64::total_cmp)
+        .context("At least two onsets are required")?;
+    Ok(max_interval * fps)
+}
+
+/// 長さを合わせるときの音価
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NoteValue {
+    #[default]
+    Beat,
+    HalfBeat,
+    /// 開始位置のBpmInfo.beat拍
+    Bar,
+}
+
+impl NoteValue {
+    fn beats(self, bpm: &aviutl2::generic::BpmInfo) -> f64 {
+        match self {
+            NoteValue::Beat => 1.0,
+            NoteValue::HalfBeat => 0.5,
+            NoteValue::Bar => bpm.beat.max(1) as f64,
+        }
+    }
+}
+
+/// spanの長さを音価の整数倍（1倍以上）に合わせたときの、終わりの次のフレームを返す
+///
+/// 長さは拍数で測るので、途中でテンポが変わる場合もそれぞれのテンポで数える。
+pub fn quantized_length_end(
+    info: &aviutl2::generic::EditInfo,
+    bpm_list: &[aviutl2::generic::BpmInfo],
+    config: &GridConfig,
+    span: std::ops::Range<f64>,
+    note: NoteValue,
+    direction: SnapDirection,
+) -> anyhow::Result<i32> {
+    quantized_length_end_at_rate(
+        bpm_list,
+        config,
+        span,
+        note,
+        direction,
+        *info.fps.numer() as f64,
+        *info.fps.denom() as f64,
+    )
+}
+
+fn quantized_length_end_at_rate(
+    bpm_list: &[aviutl2::generic::BpmInfo],
+    config: &GridConfig,
+    span: std::ops::Range<f64>,
+    note: NoteValue,
+    direction: SnapDirection,
+    rate: f64,
+    scale: f64,
+) -> anyhow::Result<i32> {
+    let segments = tempo_segments(bpm_list, config, rate, scale)?;
+    let start_segment = segments
+        .iter()
+        .rev()
+        .find(|segment| segment.start <= span.start)
+        .unwrap_or(&segments[0]);
+    let note_beats = note.beats(&start_segment.bpm);
+
+    let beats = beats_between(&segments, span.start, span.end);
+    // NOTE: 計算誤差でちょうどの長さが1つ隣の倍数にならないようにする
+    let count = match direction {
+        SnapDirection::Nearest => (beats / note_beats).round(),
+        SnapDirection::Earlier => (beats / note_beats + 1e-9).floor(),
+        SnapDirection::Later => (beats / note_beats - 1e-9).ceil(),
+    }
+    .max(1.0);
+    let frame = advance_beats(&segments, span.start, count * note_beats);
+    Ok(config.rounding.apply(frame) as i32)
+}
+
+struct TempoSegment {
+    bpm: aviutl2::generic::BpmInfo,
+    calc: BpmGridCalc,
+    // NOTE: 最初のセグメントは前に、最後のセグメントは後ろに無限に延長する
+    start: f64,
+    end: f64,
+}
+
+fn tempo_segments(
+    bpm_list: &[aviutl2::generic::BpmInfo],
+    config: &GridConfig,
+    rate: f64,
+    scale: f64,
+) -> anyhow::Result<Vec<TempoSegment>> {
+    let bpm_list = sorted_bpm_list(bpm_list)?;
+    Ok(bpm_list
+        .iter()
+        .enumerate()
+        .map(|(index, bpm)| TempoSegment {
+            bpm: *bpm,
+            calc: BpmGridCalc::new(*bpm, bpm_list.get(index + 1), rate, scale, config),
+            start: if index == 0 {
+                f64::NEG_INFINITY
+            } else {
+                bpm.start * rate / scale
+            },
+            end: bpm_list
+                .get(index + 1)
+                .map_or(f64::INFINITY, |next| next.start * rate / scale),
+        })
+        .collect())
+}
+
+fn beats_between(segments: &[TempoSegment], from: f64, to: f64) -> f64 {
+    segments
+        .iter()
+        .map(|segment| {
+            let from = from.max(segment.start);
+            let to = to.min(segment.end);
+            if from < to {
+                segment.calc.frame_to_beat(to) - segment.calc.frame_to_beat(from)
+            } else {
+                0.0
+            }
+        })
+        .sum()
+}
+
+fn advance_beats(segments: &[TempoSegment], from: f64, beats: f64) -> f64 {
+    let mut remaining = beats;
+    for segment in segments.iter().filter(|segment| segment.end > from) {
+        let from_beat = segment.calc.frame_to_beat(from.max(segment.start));
+        let available = if segment.end.is_finite() {
+            segment.calc.frame_to_beat(segment.end) - from_beat
+        } else {
+            f64::INFINITY
+        };
+        if remaining <= available {
+            return segment.calc.beat_to_frame(from_beat + remaining);
+        }
+        remaining -= available;
+    }
+    unreachable!("the last segment extends to infinity")
+}
+
+/// オブジェクトの境界などから推定したBPMグリッド
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BpmEstimate {
+    pub tempo: f64,
+    /// 最初の拍の位置（秒）
+    pub offset: f64,
+    /// 境界がどれだけ拍に揃っているか（0〜1）
+    pub score: f64,
+}
+
+impl BpmEstimate {
+    pub fn to_bpm_info(self, beat: i32) -> aviutl2::generic::BpmInfo {
+        aviutl2::generic::BpmInfo {
+            tempo: self.tempo as f32,
+            beat,
+            start: 0.0,
+            offset: self.offset as f32,
+        }
+    }
+}
+
+const ESTIMATE_MIN_TEMPO: f64 = 60.0;
+const ESTIMATE_MAX_TEMPO: f64 = 200.0;
+const ESTIMATE_COARSE_STEP: f64 = 0.05;
+const ESTIMATE_FINE_STEP: f64 = 0.001;
+const ESTIMATE_MIN_FRAMES: usize = 4;
+const ESTIMATE_PREFERRED_TEMPO: f64 = 120.0;
+
+pub fn estimate_bpm(
+    info: &aviutl2::generic::EditInfo,
+    frames: &[f64],
+    rounding: FrameRounding,
+) -> anyhow::Result<BpmEstimate> {
+    estimate_bpm_at_rate(
+        frames,
+        *info.fps.numer() as f64,
+        *info.fps.denom() as f64,
+        rounding,
+    )
+}
+
+fn estimate_bpm_at_rate(
+    frames: &[f64],
+    rate: f64,
+    scale: f64,
+    rounding: FrameRounding,
+) -> anyhow::Result<BpmEstimate> {
+    if frames.len() < ESTIMATE_MIN_FRAMES {
+        anyhow::bail!(
+            "At least {ESTIMATE_MIN_FRAMES} edges are required to estimate BPM, found {}",
+            frames.len()
+        );
+    }
+    let seconds: Vec<f64> = frames.iter().map(|&frame| frame * scale / rate).collect();
+
+    // NOTE: 各境界を拍の位相に変換し、その平均ベクトルの長さ（揃い具合）と向き（オフセット）を求める
+    let fit = |tempo: f64| {
+        let (sum_cos, sum_sin) = seconds.iter().fold((0.0, 0.0), |(sum_cos, sum_sin), &s| {
+            let angle = std::f64::consts::TAU * s * tempo / 60.0;
+            (sum_cos + angle.cos(), sum_sin + angle.sin())
+        });
+        (
+            sum_cos.hypot(sum_sin) / seconds.len() as f64,
+            sum_sin.atan2(sum_cos),
+        )
+    };
+    // NOTE: 整数倍・整数分の1のテンポも同じくらい揃うので、ほぼ同点なら120BPMに近い方を選ぶ
+    let is_better = |tempo: f64, score: f64, best: Option<(f64, f64)>| match best {
+        None => true,
+        Some((best_tempo, best_score)) => {
+            score > best_score + 1e-3
+                || (score > best_score - 1e-3
+                    && (tempo / ESTIMATE_PREFERRED_TEMPO).ln().abs()
+                        < (best_tempo / ESTIMATE_PREFERRED_TEMPO).ln().abs())
+        }
+    };
+
+    let mut coarse_best = None;
+    let coarse_steps = ((ESTIMATE_MAX_TEMPO - ESTIMATE_MIN_TEMPO) / ESTIMATE_COARSE_STEP).round();
+    for step in 0..=(coarse_steps as usize) {
+        let tempo = ESTIMATE_MIN_TEMPO + step as f64 * ESTIMATE_COARSE_STEP;
+        let (score, _) = fit(tempo);
+        if is_better(tempo, score, coarse_best) {
+            coarse_best = Some((tempo, score));
+        }
+    }
+    let (coarse_tempo, _) = coarse_best.expect("tempo range is not empty");
+
+    let mut best: Option<(f64, f64, f64)> = None;
+    let fine_range = ESTIMATE_COARSE_STEP * 2.0;
+    let fine_steps = (fine_range * 2.0 / ESTIMATE_FINE_STEP).round() as usize;
+    for step in 0..=fine_steps {
+        let tempo = coarse_tempo - fine_range + step as f64 * ESTIMATE_FINE_STEP;
+        let (score, angle) = fit(tempo);
+        if best.is_none_or(|(_, best_score, _)| score > best_score) {
+            best = Some((tempo, score, angle));
+        }
+    }
+    let (tempo, score, angle) = best.expect("fine range is not empty");
+
+    // NOTE: 拍の近くにある境界だけで、拍番号と秒の直線を最小二乗法で引き直して精度を上げる
+    let phase = angle / std::f64::consts::TAU;
+    let inliers: Vec<(f64, f64)> = seconds
+        .iter()
+        .filter_map(|&s| {
+            let beat = s * tempo / 60.0 - phase;
+            ((beat - beat.round()).abs() < 0.25).then_some((beat.round(), s))
+        })
+        .collect();
+    let (seconds_per_beat, first_beat) = fit_line(&inliers).unwrap_or((60.0 / tempo, 0.0));
+
+    // NOTE: 境界のフレームは拍の瞬間を丸めたものなので、丸め方に応じて拍の瞬間の位置に戻す
+    let rounding_shift = match rounding {
+        FrameRounding::Ceil => -0.5,
+        FrameRounding::Round => 0.0,
+        FrameRounding::Floor => 0.5,
+    };
+    let offset = (first_beat + rounding_shift * scale / rate).rem_euclid(seconds_per_beat);
+    let tempo = 60.0 / seconds_per_beat;
+    Ok(BpmEstimate {
+        tempo,
+        offset,
+        score,
+    })
+}
+
+/// (x, y)の組に直線を当てはめて、(傾き, 切片)を返す
+fn fit_line(points: &[(f64, f64)]) -> Option<(f64, f64)> {
+    if points.len() < 2 {
+        return None;
+    }
+    let count = points.len() as f64;
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / count;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / count;
+    let variance = points
+        .iter()
+        .map(|(x, _)| (x - mean_x).powi(2))
+        .sum::<f64>();
+    if variance <= 0.0 {
+        return None;
+    }
+    let covariance = points
+        .iter()
+        .map(|(x, y)| (x - mean_x) * (y - mean_y))
+        .sum::<f64>();
+    let slope = covariance / variance;
+    (slope > 0.0).then_some((slope, mean_y - slope * mean_x))
+}
+
+pub const TICKS_PER_BEAT: u32 = 480;
+
+/// 小節・拍・tickで表した位置。小節と拍は1始まり
+///
+/// 最初の小節線より前は0小節目（弱起）になる。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MusicalTime {
+    pub bar: i64,
+    pub beat: u32,
+    pub tick: u32,
+}
+
+impl MusicalTime {
+    fn from_beats_in_bar(bar: i64, beats_in_bar: f64) -> Self {
+        let ticks = (beats_in_bar.max(0.0) * TICKS_PER_BEAT as f64).round() as u64;
+        Self {
+            bar,
+            beat: (ticks / TICKS_PER_BEAT as u64) as u32 + 1,
+            tick: (ticks % TICKS_PER_BEAT as u64) as u32,
+        }
+    }
+
+    fn beats_in_bar(&self) -> f64 {
+        self.beat.saturating_sub(1) as f64 + self.tick as f64 / TICKS_PER_BEAT as f64
+    }
+}
+
+struct BarSegment {
+    calc: BpmGridCalc,
+    start_beat: f64,
+    beats_per_bar: f64,
+    // NOTE: このセグメントで最初の小節線（何小節目か、ではなくセグメント内の小節の番号）
+    first_bar_line: f64,
+    // セグメント開始時点で進行中の小節と、その小節の頭からの拍数
+    bar_at_start: i64,
+    beats_into_bar_at_start: f64,
+}
+
+impl BarSegment {
+    fn musical_time(&self, beat_num: f64) -> MusicalTime {
+        // NOTE: tickに丸めると小節線になる位置は、次の小節の頭として扱う
+        let half_tick = 0.5 / TICKS_PER_BEAT as f64;
+        let bar_line = ((beat_num + half_tick) / self.beats_per_bar).floor();
+        if bar_line < self.first_bar_line {
+            MusicalTime::from_beats_in_bar(
+                self.bar_at_start,
+                self.beats_into_bar_at_start + beat_num - self.start_beat,
+            )
+        } else {
+            MusicalTime::from_beats_in_bar(
+                self.bar_at_start + (bar_line - self.first_bar_line) as i64 + 1,
+                beat_num - bar_line * self.beats_per_bar,
+            )
+        }
+    }
+}
+
+fn bar_segments(
+    bpm_list: &[aviutl2::generic::BpmInfo],
+    config: &GridConfig,
+    rate: f64,
+    scale: f64,
+) -> anyhow::Result<Vec<BarSegment>> {
+    let bpm_list = sorted_bpm_list(bpm_list)?;
+
+    let mut segments: Vec<BarSegment> = Vec::with_capacity(bpm_list.len());
+    for (index, bpm) in bpm_list.iter().enumerate() {
+        let calc = BpmGridCalc::new(*bpm, bpm_list.get(index + 1), rate, scale, config);
+        let start_frame = bpm.start * rate / scale;
+        let start_beat = calc.frame_to_beat(start_frame);
+        let beats_per_bar = bpm.beat.max(1) as f64;
+        let first_bar_line = (start_beat / beats_per_bar).ceil();
+        let (bar_at_start, beats_into_bar_at_start) = match segments.last() {
+            Some(prev) => {
+                let prev_end_beat = prev.calc.frame_to_beat(start_frame);
+                let last_bar_line = (prev_end_beat / prev.beats_per_bar).ceil() - 1.0;
+                if last_bar_line >= prev.first_bar_line {
+                    (
+                        prev.bar_at_start + (last_bar_line - prev.first_bar_line) as i64 + 1,
+                        prev_end_beat - last_bar_line * prev.beats_per_bar,
+                    )
+                } else {
+                    (
+                        prev.bar_at_start,
+                        prev.beats_into_bar_at_start + prev_end_beat - prev.start_beat,
+                    )
                 }
-                _ => (candidate, distance),
-            });
+            }
+            None => (0, start_beat - (first_bar_line - 1.0) * beats_per_bar),
+        };
+        segments.push(BarSegment {
+            calc,
+            start_beat,
+            beats_per_bar,
+            first_bar_line,
+            bar_at_start,
+            beats_into_bar_at_start,
+        });
+    }
+    Ok(segments)
+}
+
+pub fn frame_to_musical_time(
+    info: &aviutl2::generic::EditInfo,
+    bpm_list: &[aviutl2::generic::BpmInfo],
+    config: &GridConfig,
+    frame_num: f64,
+) -> anyhow::Result<MusicalTime> {
+    frame_to_musical_time_at_rate(
+        bpm_list,
+        config,
+        frame_num,
+        *info.fps.numer() as f64,
+        *info.fps.denom() as f64,
+    )
+}
+
+pub fn musical_time_to_frame(
+    info: &aviutl2::generic::EditInfo,
+    bpm_list: &[aviutl2::generic::BpmInfo],
+    config: &GridConfig,
+    time: MusicalTime,
+) -> anyhow::Result<f64> {
+    musical_time_to_frame_at_rate(
+        bpm_list,
+        config,
+        time,
+        *info.fps.numer() as f64,
+        *info.fps.denom() as f64,
+    )
+}
+
+fn frame_to_musical_time_at_rate(
+    bpm_list: &[aviutl2::generic::BpmInfo],
+    config: &GridConfig,
+    frame_num: f64,
+    rate: f64,
+    scale: f64,
+) -> anyhow::Result<MusicalTime> {
+    let segments = bar_segments(bpm_list, config, rate, scale)?;
+    // NOTE: 最初のセグメントより前は最初のセグメントを延長して扱う
+    let segment = segments
+        .iter()
+        .rev()
+        .find(|segment| segment.calc.frame_to_beat(frame_num) >= segment.start_beat)
+        .unwrap_or(&segments[0]);
+    Ok(segment.musical_time(segment.calc.frame_to_beat(frame_num)))
+}
+
+fn musical_time_to_frame_at_rate(
+    bpm_list: &[aviutl2::generic::BpmInfo],
+    config: &GridConfig,
+    time: MusicalTime,
+    rate: f64,
+    scale: f64,
+) -> anyhow::Result<f64> {
+    if time.beat == 0 || time.tick >= TICKS_PER_BEAT {
+        anyhow::bail!(
+            "Invalid beat or tick: beat {}, tick {}",
+            time.beat,
+            time.tick
+        );
+    }
+    let segments = bar_segments(bpm_list, config, rate, scale)?;
+    let frame = bar_segments_frame(&segments, time);
+    // NOTE: 小節の長さはテンポの切り替わりで変わるので、次の小節の頭より前にあるかで確かめる
+    let next_bar_frame = bar_segments_frame(
+        &segments,
+        MusicalTime {
+            bar: time.bar + 1,
+            beat: 1,
+            tick: 0,
+        },
+    );
+    if frame >= next_bar_frame - 1e-6 {
+        anyhow::bail!(
+            "Beat {} tick {} is beyond the end of bar {}",
+            time.beat,
+            time.tick,
+            time.bar
+        );
+    }
+    Ok(frame)
+}
+
+fn bar_segments_frame(segments: &[BarSegment], time: MusicalTime) -> f64 {
+    let beats_in_bar = time.beats_in_bar();
+    for segment in segments.iter().rev() {
+        if time.bar > segment.bar_at_start {
+            let bar_line = segment.first_bar_line + (time.bar - segment.bar_at_start - 1) as f64;
+            return segment
+                .calc
+                .beat_to_frame(bar_line * segment.beats_per_bar + beats_in_bar);
+        }
+        if time.bar == segment.bar_at_start && beats_in_bar >= segment.beats_into_bar_at_start {
+            return segment.calc.beat_to_frame(
+                segment.start_beat + beats_in_bar - segment.beats_into_bar_at_start,
+            );
         }
     }
 
-    let nearest_frame = nearest_frame
-        .map(|(frame, _)| frame as i32)
-        .context("No BPM grid candidate found")?;
-    Ok(nearest_frame)
+    let segment = &segments[0];
+    let bar_line = segment.first_bar_line - 1.0 + time.bar as f64;
+    segment
+        .calc
+        .beat_to_frame(bar_line * segment.beats_per_bar + beats_in_bar)
 }
 
 #[cfg(test)]
@@ -153,26 +1176,619 @@ mod tests {
     fn nearest_grid_frame_uses_offset_relative_to_each_bpm_start() {
         let bpm_list = [bpm(120.0, 0.0, 0.0), bpm(60.0, 10.0, 0.25)];
 
-        let nearest = nearest_grid_frame_at_rate(&bpm_list, 11.2 * 30.0, 30.0, 1.0).unwrap();
+        let nearest = nearest_grid_frame_at_rate(
+            &bpm_list,
+            &GridConfig::default(),
+            11.2 * 30.0,
+            30.0,
+            1.0,
+            SnapDirection::Nearest,
+        )
+        .unwrap();
 
-        assert_eq!(nearest, 338);
+        assert_eq!(nearest, Some(338));
     }
 
     #[test]
     fn nearest_grid_frame_does_not_use_previous_segment_after_next_start() {
         let bpm_list = [bpm(120.0, 0.0, 0.0), bpm(60.0, 10.0, 0.25)];
 
-        let nearest = nearest_grid_frame_at_rate(&bpm_list, 10.1 * 30.0, 30.0, 1.0).unwrap();
+        let nearest = nearest_grid_frame_at_rate(
+            &bpm_list,
+            &GridConfig::default(),
+            10.1 * 30.0,
+            30.0,
+            1.0,
+            SnapDirection::Nearest,
+        )
+        .unwrap();
 
-        assert_eq!(nearest, 308);
+        assert_eq!(nearest, Some(308));
     }
 
     #[test]
     fn nearest_grid_frame_uses_rounded_bpm_segment_boundary() {
         let bpm_list = [bpm(120.0, 0.0, 0.005), bpm(60.0, 10.01, 0.25)];
 
-        let nearest = nearest_grid_frame_at_rate(&bpm_list, 301.0, 30.0, 1.0).unwrap();
+        let nearest = nearest_grid_frame_at_rate(
+            &bpm_list,
+            &GridConfig::default(),
+            301.0,
+            30.0,
+            1.0,
+            SnapDirection::Nearest,
+        )
+        .unwrap();
+
+        assert_eq!(nearest, Some(308));
+
+        // NOTE: 1つ目のBPM情報の最後の拍は300.15フレーム目、2つ目のBPM情報は300.6フレーム目から
+        //       始まり、その最初の拍は308.1フレーム目
+        let bpm_list = [bpm(120.0, 0.0, 0.005), bpm(60.0, 10.02, 0.25)];
+        for (rounding, expected) in [
+            (FrameRounding::Ceil, 309),
+            (FrameRounding::Round, 300),
+            (FrameRounding::Floor, 308),
+        ] {
+            let config = GridConfig {
+                rounding,
+                ..Default::default()
+            };
+            let nearest = nearest_grid_frame_at_rate(
+                &bpm_list,
+                &config,
+                301.0,
+                30.0,
+                1.0,
+                SnapDirection::Nearest,
+            )
+            .unwrap();
+            assert_eq!(nearest, Some(expected), "{rounding:?}");
+        }
+    }
+
+    #[test]
+    fn nearest_grid_frame_rounds_beat_instants_by_policy() {
+        let bpm_list = [bpm(128.0, 0.0, 0.0)];
+        // NOTE: 30fps・128BPMでは1拍が14.0625フレームになる
+        for (rounding, expected) in [
+            (FrameRounding::Ceil, [15, 29, 43, 113]),
+            (FrameRounding::Round, [14, 28, 42, 113]),
+            (FrameRounding::Floor, [14, 28, 42, 112]),
+        ] {
+            let config = GridConfig {
+                rounding,
+                ..Default::default()
+            };
+            for (beat, expected) in [1.0, 2.0, 3.0, 8.0].into_iter().zip(expected) {
+                let frame = (beat * 14.0625_f64).round();
+                let nearest = nearest_grid_frame_at_rate(
+                    &bpm_list,
+                    &config,
+                    frame,
+                    30.0,
+                    1.0,
+                    SnapDirection::Nearest,
+                )
+                .unwrap();
+                assert_eq!(nearest, Some(expected), "{rounding:?}, beat {beat}");
+            }
+        }
+    }
+
+    #[test]
+    fn nearest_grid_frame_respects_snap_direction() {
+        let bpm_list = [bpm(120.0, 0.0, 0.0)];
+        let config = GridConfig::default();
+
+        let earlier =
+            nearest_grid_frame_at_rate(&bpm_list, &config, 20.0, 30.0, 1.0, SnapDirection::Earlier)
+                .unwrap();
+        assert_eq!(earlier, Some(15));
+
+        let later =
+            nearest_grid_frame_at_rate(&bpm_list, &config, 16.0, 30.0, 1.0, SnapDirection::Later)
+                .unwrap();
+        assert_eq!(later, Some(30));
+
+        let on_grid =
+            nearest_grid_frame_at_rate(&bpm_list, &config, 30.0, 30.0, 1.0, SnapDirection::Later)
+                .unwrap();
+        assert_eq!(on_grid, Some(30));
+
+        let offset_list = [bpm(120.0, 0.0, 0.2)];
+        let none = nearest_grid_frame_at_rate(
+            &offset_list,
+            &config,
+            0.0,
+            30.0,
+            1.0,
+            SnapDirection::Earlier,
+        )
+        .unwrap();
+        assert_eq!(none, None);
+    }
+
+    #[test]
+    fn quantized_length_end_rounds_to_note_multiples() {
+        let bpm_list = [bpm(120.0, 0.0, 0.0)];
+        let config = GridConfig::default();
+        let end = |end: f64, note: NoteValue, direction: SnapDirection| {
+            quantized_length_end_at_rate(&bpm_list, &config, 10.0..end, note, direction, 30.0, 1.0)
+                .unwrap()
+        };
+
+        assert_eq!(end(38.0, NoteValue::Beat, SnapDirection::Nearest), 40);
+        assert_eq!(end(38.0, NoteValue::Beat, SnapDirection::Earlier), 25);
+        assert_eq!(end(38.0, NoteValue::HalfBeat, SnapDirection::Nearest), 40);
+        assert_eq!(end(30.0, NoteValue::HalfBeat, SnapDirection::Nearest), 33);
+        assert_eq!(end(50.0, NoteValue::Bar, SnapDirection::Nearest), 70);
+        assert_eq!(end(12.0, NoteValue::Beat, SnapDirection::Earlier), 25);
+    }
+
+    #[test]
+    fn quantized_length_end_counts_beats_across_tempo_changes() {
+        let bpm_list = [bpm(120.0, 0.0, 0.0), bpm(60.0, 10.0, 0.0)];
+
+        let end = quantized_length_end_at_rate(
+            &bpm_list,
+            &GridConfig::default(),
+            292.5..310.0,
+            NoteValue::Beat,
+            SnapDirection::Nearest,
+            30.0,
+            1.0,
+        )
+        .unwrap();
+
+        assert_eq!(end, 315);
+    }
+
+    #[test]
+    fn frames_per_beat_at_follows_each_segment() {
+        let bpm_list = [bpm(120.0, 0.0, 0.0), bpm(60.0, 10.0, 0.0)];
+
+        let frames =
+            frames_per_beat_at_rate(&bpm_list, &GridConfig::default(), 150.0, 30.0, 1.0).unwrap();
+        assert!((frames - 15.0).abs() < 1e-9, "{frames}");
+
+        let frames =
+            frames_per_beat_at_rate(&bpm_list, &GridConfig::default(), 450.0, 30.0, 1.0).unwrap();
+        assert!((frames - 30.0).abs() < 1e-9, "{frames}");
+
+        let config = GridConfig {
+            tempo_ramp: TempoRamp::Linear,
+            ..Default::default()
+        };
+        let bpm_list = [bpm(120.0, 0.0, 0.0), bpm(240.0, 10.0, 0.0)];
+        let frames = frames_per_beat_at_rate(&bpm_list, &config, 150.0, 30.0, 1.0).unwrap();
+        assert!((frames - 10.0).abs() < 0.01, "{frames}");
+    }
+
+    #[test]
+    fn nearest_grid_frame_snaps_to_subdivisions() {
+        let bpm_list = [bpm(120.0, 0.0, 0.0)];
+        let config = GridConfig {
+            subdivisions: vec![4],
+            ..Default::default()
+        };
+
+        let nearest =
+            nearest_grid_frame_at_rate(&bpm_list, &config, 26.0, 30.0, 1.0, SnapDirection::Nearest)
+                .unwrap();
+        assert_eq!(nearest, Some(27));
+
+        let nearest =
+            nearest_grid_frame_at_rate(&bpm_list, &config, 24.0, 30.0, 1.0, SnapDirection::Nearest)
+                .unwrap();
+        assert_eq!(nearest, Some(23));
+    }
+
+    #[test]
+    fn nearest_grid_frame_snaps_to_tuplets() {
+        let bpm_list = [bpm(120.0, 0.0, 0.0)];
+        let config = GridConfig {
+            subdivisions: vec![3],
+            ..Default::default()
+        };
+
+        let nearest =
+            nearest_grid_frame_at_rate(&bpm_list, &config, 6.0, 30.0, 1.0, SnapDirection::Nearest)
+                .unwrap();
+
+        assert_eq!(nearest, Some(5));
+    }
+
+    #[test]
+    fn nearest_grid_frame_uses_closest_of_mixed_subdivisions() {
+        let bpm_list = [bpm(120.0, 0.0, 0.0)];
+        let config = GridConfig {
+            subdivisions: vec![4, 6],
+            ..Default::default()
+        };
+
+        let nearest =
+            nearest_grid_frame_at_rate(&bpm_list, &config, 10.0, 30.0, 1.0, SnapDirection::Nearest)
+                .unwrap();
+        assert_eq!(nearest, Some(10));
+
+        let nearest =
+            nearest_grid_frame_at_rate(&bpm_list, &config, 4.0, 30.0, 1.0, SnapDirection::Nearest)
+                .unwrap();
+        assert_eq!(nearest, Some(4));
+    }
+
+    #[test]
+    fn nearest_grid_frame_moves_offbeat_subdivisions_by_swing() {
+        let bpm_list = [bpm(120.0, 0.0, 0.0)];
+        let straight = GridConfig {
+            subdivisions: vec![2],
+            ..Default::default()
+        };
+        let swung = GridConfig {
+            swing: 2.0 / 3.0,
+            ..straight.clone()
+        };
+
+        let nearest = nearest_grid_frame_at_rate(
+            &bpm_list,
+            &straight,
+            9.0,
+            30.0,
+            1.0,
+            SnapDirection::Nearest,
+        )
+        .unwrap();
+        assert_eq!(nearest, Some(8));
+
+        let nearest =
+            nearest_grid_frame_at_rate(&bpm_list, &swung, 9.0, 30.0, 1.0, SnapDirection::Nearest)
+                .unwrap();
+        assert_eq!(nearest, Some(10));
+
+        let nearest =
+            nearest_grid_frame_at_rate(&bpm_list, &swung, 14.0, 30.0, 1.0, SnapDirection::Nearest)
+                .unwrap();
+        assert_eq!(nearest, Some(15));
+    }
+
+    #[test]
+    fn nearest_grid_frame_does_not_swing_odd_subdivisions() {
+        let bpm_list = [bpm(120.0, 0.0, 0.0)];
+        let config = GridConfig {
+            subdivisions: vec![3],
+            swing: 2.0 / 3.0,
+            ..Default::default()
+        };
+
+        // NOTE: 3連符のグリッドは5、10、15フレーム目のまま
+        for (frame, expected) in [(4.0, 5), (9.0, 10), (16.0, 15), (19.0, 20)] {
+            let nearest = nearest_grid_frame_at_rate(
+                &bpm_list,
+                &config,
+                frame,
+                30.0,
+                1.0,
+                SnapDirection::Nearest,
+            )
+            .unwrap();
+            assert_eq!(nearest, Some(expected), "{frame}");
+        }
+
+        let mixed = GridConfig {
+            subdivisions: vec![2, 3],
+            ..config.clone()
+        };
+        assert!((mixed.min_beat_interval(&bpm_list) - 1.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn nearest_grid_frame_snaps_to_bar_lines() {
+        let bpm_list = [bpm(120.0, 0.0, 0.0), bpm(120.0, 10.0, 0.5)];
+        let config = GridConfig {
+            subdivisions: vec![4],
+            snap_to_bar: true,
+            ..Default::default()
+        };
+
+        let nearest =
+            nearest_grid_frame_at_rate(&bpm_list, &config, 40.0, 30.0, 1.0, SnapDirection::Nearest)
+                .unwrap();
+        assert_eq!(nearest, Some(60));
+
+        let nearest =
+            nearest_grid_frame_at_rate(&bpm_list, &config, 25.0, 30.0, 1.0, SnapDirection::Nearest)
+                .unwrap();
+        assert_eq!(nearest, Some(0));
+
+        let nearest = nearest_grid_frame_at_rate(
+            &bpm_list,
+            &config,
+            320.0,
+            30.0,
+            1.0,
+            SnapDirection::Nearest,
+        )
+        .unwrap();
+        assert_eq!(nearest, Some(315));
+
+        let three_four = [aviutl2::generic::BpmInfo {
+            beat: 3,
+            ..bpm_list[1]
+        }];
+        assert_eq!(config.min_beat_interval(&bpm_list), 4.0);
+        assert_eq!(config.min_beat_interval(&three_four), 3.0);
+    }
+
+    #[test]
+    fn frame_to_musical_time_counts_bars_across_tempo_changes() {
+        let bpm_list = [bpm(120.0, 0.0, 0.0), bpm(60.0, 10.0, 0.25)];
+
+        let time = frame_to_musical_time_at_rate(&bpm_list, &GridConfig::default(), 0.0, 30.0, 1.0)
+            .unwrap();
+        assert_eq!(
+            time,
+            MusicalTime {
+                bar: 1,
+                beat: 1,
+                tick: 0
+            }
+        );
+
+        let time =
+            frame_to_musical_time_at_rate(&bpm_list, &GridConfig::default(), 100.0, 30.0, 1.0)
+                .unwrap();
+        assert_eq!(
+            time,
+            MusicalTime {
+                bar: 2,
+                beat: 3,
+                tick: 320
+            }
+        );
+
+        let time =
+            frame_to_musical_time_at_rate(&bpm_list, &GridConfig::default(), 303.0, 30.0, 1.0)
+                .unwrap();
+        assert_eq!(
+            time,
+            MusicalTime {
+                bar: 5,
+                beat: 5,
+                tick: 48
+            }
+        );
+
+        let time =
+            frame_to_musical_time_at_rate(&bpm_list, &GridConfig::default(), 337.5, 30.0, 1.0)
+                .unwrap();
+        assert_eq!(
+            time,
+            MusicalTime {
+                bar: 6,
+                beat: 2,
+                tick: 0
+            }
+        );
+    }
+
+    #[test]
+    fn musical_time_to_frame_is_inverse_of_frame_to_musical_time() {
+        let bpm_list = [
+            bpm(120.0, 0.0, 0.1),
+            bpm(60.0, 10.0, 0.25),
+            bpm(180.0, 20.0, 0.0),
+        ];
+
+        for frame in [0.0, 3.0, 100.0, 303.0, 337.5, 599.0, 620.0, 1000.0] {
+            let time =
+                frame_to_musical_time_at_rate(&bpm_list, &GridConfig::default(), frame, 30.0, 1.0)
+                    .unwrap();
+            let restored =
+                musical_time_to_frame_at_rate(&bpm_list, &GridConfig::default(), time, 30.0, 1.0)
+                    .unwrap();
+            assert!(
+                (restored - frame).abs() < 0.1,
+                "{frame} -> {time:?} -> {restored}"
+            );
+        }
+    }
+
+    #[test]
+    fn musical_time_to_frame_rejects_positions_outside_the_bar() {
+        let bpm_list = [bpm(120.0, 0.0, 0.0), bpm(60.0, 10.0, 0.25)];
+        let to_frame = |bar, beat, tick| {
+            musical_time_to_frame_at_rate(
+                &bpm_list,
+                &GridConfig::default(),
+                MusicalTime { bar, beat, tick },
+                30.0,
+                1.0,
+            )
+        };
+
+        assert!(to_frame(2, 5, 0).is_err());
+        assert!(to_frame(2, 0, 0).is_err());
+        assert!(to_frame(2, 1, TICKS_PER_BEAT).is_err());
+        assert!((to_frame(3, 1, 0).unwrap() - 120.0).abs() < 1e-6);
+        // NOTE: テンポが切り替わる小節は、次のBPM情報の最初の小節線まで続く
+        assert!((to_frame(5, 5, 0).unwrap() - 300.0).abs() < 1e-6);
+        assert!(to_frame(5, 5, 240).is_err());
+    }
+
+    #[test]
+    fn bpm_grid_segments_set_offset_matches_rebuilt_grid() {
+        let config = GridConfig {
+            tempo_ramp: TempoRamp::Linear,
+            ..Default::default()
+        };
+        let mut bpm_list = [bpm(60.0, 0.0, 0.0), bpm(120.0, 10.0, 0.0)];
+        let mut segments = BpmGridSegments::new_at_rate(&bpm_list, &config, 30.0, 1.0).unwrap();
+        bpm_list[0].offset = 0.3;
+        segments.set_offset(0, 0.3);
+
+        for frame in [20.0, 101.0, 120.0, 250.0, 400.0] {
+            let expected = nearest_grid_frame_at_rate(
+                &bpm_list,
+                &config,
+                frame,
+                30.0,
+                1.0,
+                SnapDirection::Nearest,
+            )
+            .unwrap();
+            let nearest = segments
+                .nearest_frame(frame, SnapDirection::Nearest, None)
+                .map(|nearest| nearest.frame());
+            assert_eq!(nearest, expected, "frame {frame}");
+        }
+    }
+
+    #[test]
+    fn nearest_grid_frame_follows_linear_tempo_ramp() {
+        let bpm_list = [bpm(60.0, 0.0, 0.0), bpm(120.0, 10.0, 0.0)];
+        let config = GridConfig {
+            tempo_ramp: TempoRamp::Linear,
+            ..Default::default()
+        };
+
+        let nearest = nearest_grid_frame_at_rate(
+            &bpm_list,
+            &config,
+            120.0,
+            30.0,
+            1.0,
+            SnapDirection::Nearest,
+        )
+        .unwrap();
+        assert_eq!(nearest, Some(125));
+
+        let nearest = nearest_grid_frame_at_rate(
+            &bpm_list,
+            &config,
+            101.0,
+            30.0,
+            1.0,
+            SnapDirection::Nearest,
+        )
+        .unwrap();
+        assert_eq!(nearest, Some(103));
+    }
+
+    #[test]
+    fn tempo_ramp_frame_and_beat_are_inverse() {
+        let bpm_list = [bpm(60.0, 0.0, 0.2), bpm(150.0, 10.0, 0.0)];
+        for (tempo_ramp, beats_at_end) in [
+            (TempoRamp::Linear, 17.5),
+            (TempoRamp::Exponential, 15.0 / 2.5f64.ln()),
+        ] {
+            let config = GridConfig {
+                tempo_ramp,
+                ..Default::default()
+            };
+            let calc = BpmGridCalc::new(bpm_list[0], Some(&bpm_list[1]), 30.0, 1.0, &config);
+
+            assert!((calc.frame_to_beat(300.0) - (beats_at_end - 0.2)).abs() < 1e-6);
+            for frame in [0.0, 6.0, 50.0, 150.0, 299.0, 300.0, 450.0] {
+                let restored = calc.beat_to_frame(calc.frame_to_beat(frame));
+                assert!(
+                    (restored - frame).abs() < 1e-6,
+                    "{tempo_ramp:?}: {frame} -> {restored}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn onset_interval_frames_at_uses_median_outside_onsets() {
+        let onsets = [1.0, 1.5, 2.5, 3.0];
+
+        assert_eq!(
+            onset_interval_frames_at_rate(&onsets, 60.0, 30.0, 1.0).unwrap(),
+            30.0
+        );
+        assert_eq!(
+            onset_interval_frames_at_rate(&onsets, 0.0, 30.0, 1.0).unwrap(),
+            15.0
+        );
+        assert_eq!(
+            onset_interval_frames_at_rate(&onsets, 120.0, 30.0, 1.0).unwrap(),
+            15.0
+        );
+        assert_eq!(
+            onset_interval_frames_at_rate(&onsets, 40.0, 30.0, 1.0).unwrap(),
+            15.0
+        );
+        assert!(onset_interval_frames_at_rate(&[1.0], 30.0, 30.0, 1.0).is_err());
+    }
+
+    #[test]
+    fn nearest_onset_frame_uses_closest_onset() {
+        let onsets = [0.5, 1.0, 1.75];
+
+        let nearest = nearest_onset_frame_at_rate(
+            &onsets,
+            20.0,
+            30.0,
+            1.0,
+            FrameRounding::Ceil,
+            SnapDirection::Nearest,
+        );
+        assert_eq!(nearest, Some(15));
+
+        let nearest = nearest_onset_frame_at_rate(
+            &onsets,
+            40.0,
+            30.0,
+            1.0,
+            FrameRounding::Ceil,
+            SnapDirection::Nearest,
+        );
+        assert_eq!(nearest, Some(30));
+
+        let nearest = nearest_onset_frame_at_rate(
+            &onsets,
+            45.0,
+            30.0,
+            1.0,
+            FrameRounding::Ceil,
+            SnapDirection::Nearest,
+        );
+        assert_eq!(nearest, Some(53));
+
+        let nearest = nearest_onset_frame_at_rate(
+            &onsets,
+            100.0,
+            30.0,
+            1.0,
+            FrameRounding::Ceil,
+            SnapDirection::Nearest,
+        );
+        assert_eq!(nearest, Some(53));
+    }
+
+    #[test]
+    fn estimate_bpm_recovers_tempo_and_offset() {
+        let mut frames: Vec<f64> = (0..64)
+            .filter(|beat| beat % 3 != 1)
+            .map(|beat| ((0.25 + beat as f64 * 60.0 / 128.0) * 30.0).ceil())
+            .collect();
+        frames.extend([17.0, 101.0]);
+
+        let estimate = estimate_bpm_at_rate(&frames, 30.0, 1.0, FrameRounding::Ceil).unwrap();
+
+        assert!((estimate.tempo - 128.0).abs() < 0.05, "{estimate:?}");
+        assert!((estimate.offset - 0.25).abs() < 1.0 / 30.0, "{estimate:?}");
+        assert!(estimate.score > 0.9, "{estimate:?}");
+    }
+
+    #[test]
+    fn estimate_bpm_prefers_tempo_near_120() {
+        let frames: Vec<f64> = (0..16).map(|second| second as f64 * 30.0).collect();
+
+        let estimate = estimate_bpm_at_rate(&frames, 30.0, 1.0, FrameRounding::Ceil).unwrap();
+        assert!((estimate.tempo - 120.0).abs() < 0.01, "{estimate:?}");
 
-        assert_eq!(nearest, 308);
+        assert!(estimate_bpm_at_rate(&frames[..2], 30.0, 1.0, FrameRounding::Ceil).is_err());
     }
 }
@@ -2,29 +2,106 @@ use aviutl2::{anyhow, config::translate as tr, tracing};
 use aviutl2_eframe::{AviUtl2EframeHandle, eframe, egui};
 use std::sync::atomic::Ordering;
 
+#[derive(PartialEq, Eq, Clone, Copy)]
+enum GridSourceKind {
+    Project,
+    TempoMap,
+    Audio,
+}
+
+/// 補正内容の確認での1つのズレ
+struct FixReviewRow {
+    /// 隣とぶつかるものの一覧から補正する場合はtrue
+    blocked: bool,
+    /// このズレの補正で一緒に補正されるズレ
+    covered_keys: Vec<crate::find::TimingKey>,
+    plan: crate::find::FixPlan,
+    accepted: bool,
+}
+
+/// プロジェクトのBPMグリッドを書き換える前の確認
+struct BpmGridConfirm {
+    current: Vec<aviutl2::generic::BpmInfo>,
+    new: Vec<aviutl2::generic::BpmInfo>,
+}
+
 #[derive(PartialEq, Eq)]
 enum SortBy {
     Layer,
     Frame,
 }
 
+impl SortBy {
+    fn key(&self) -> fn(&crate::find::OffbeatInfo) -> (usize, usize) {
+        match self {
+            SortBy::Layer => |gap| (gap.position.layer, gap.frame),
+            SortBy::Frame => |gap| (gap.frame, gap.position.layer),
+        }
+    }
+}
+
 pub(crate) struct QuantizerGuiApp {
     handle: AviUtl2EframeHandle,
     show_info: bool,
     suppress_info_close_once: bool,
     header_collapsed: bool,
     version: String,
-    frame_count: usize,
+    tolerance_unit: crate::find::ToleranceUnit,
+    grid_source_kind: GridSourceKind,
+    tempo_map: Option<(std::path::PathBuf, Vec<aviutl2::generic::BpmInfo>)>,
+    onsets: Option<(std::path::PathBuf, std::sync::Arc<[f64]>)>,
+    /// 解析中の音声ファイル
+    onset_loading: Option<(
+        std::path::PathBuf,
+        std::sync::mpsc::Receiver<anyhow::Result<Vec<f64>>>,
+    )>,
+    audio_start_frame: usize,
+    bpm_estimate: Option<crate::grid::BpmEstimate>,
+    offset_fit: Option<(GridSourceKind, crate::find::GridOffsetFit)>,
+    /// オフセットを合わせている途中のグリッドの取得元
+    offset_fitting: Option<(
+        GridSourceKind,
+        std::sync::mpsc::Receiver<anyhow::Result<crate::find::GridOffsetFit>>,
+    )>,
+    bpm_grid_confirm: Option<BpmGridConfirm>,
+    subdivision: u32,
+    mix_subdivision: bool,
+    mixed_subdivision: u32,
+    swing_percent: f64,
+    snap_to_bar: bool,
+    tempo_ramp: crate::grid::TempoRamp,
+    rounding: crate::grid::FrameRounding,
     target_start: bool,
     target_middle: bool,
     target_end: bool,
     target_project_end: bool,
+    target_downbeat_only: bool,
+    min_offset_frames: u64,
+    start_move: crate::find::StartMove,
+    stretch_keyframes: bool,
+    join_gaps: bool,
+    length_mode: bool,
+    length_note: crate::grid::NoteValue,
+    target_start_setting: TimingSetting,
+    target_middle_setting: TimingSetting,
+    target_end_setting: TimingSetting,
     sort_by: SortBy,
     auto_jump: bool,
 
     selected_gap_index: usize,
 
     gaps: Option<Vec<crate::find::OffbeatInfo>>,
+    /// 補正すると隣とぶつかるもの
+    blocked_gaps: Vec<crate::find::OffbeatInfo>,
+    fix_failures: Vec<(crate::find::OffbeatInfo, String)>,
+    fix_review: Option<Vec<FixReviewRow>>,
+    /// 検出してから補正した内容
+    applied_fixes: Vec<FixReviewRow>,
+    // NOTE: ズレのカードごとの補正は別々の編集になるので、検出してから行った補正を
+    //       ここに記録して、1回の編集でまとめて戻せるようにする
+    fix_history: Vec<crate::find::PlannedMove>,
+    /// 検出に使った設定。gapsがあるときは必ずある
+    fix_context: Option<crate::find::FixContext>,
 }
 
 fn tr_format(template: &str, args: &[(&str, &str)]) -> String {
@@ -36,6 +113,357 @@ fn tr_format(template: &str, args: &[(&str, &str)]) -> String {
     translated
 }
 
+const SUBDIVISIONS: [u32; 8] = [1, 2, 4, 8, 16, 3, 6, 12];
+
+fn subdivision_label(subdivision: u32) -> String {
+    if subdivision == 1 {
+        tr("1拍")
+    } else {
+        tr_format(
+            "1/{subdivision}拍",
+            &[("subdivision", &subdivision.to_string())],
+        )
+    }
+}
+
+fn subdivision_selector(ui: &mut egui::Ui, id: &str, subdivision: &mut u32) {
+    ui.horizontal(|ui| {
+        let drag_width = 48.0;
+        egui::ComboBox::from_id_salt(id)
+            .width(ui.available_width() - drag_width - ui.spacing().item_spacing.x)
+            .selected_text(subdivision_label(*subdivision))
+            .show_ui(ui, |ui| {
+                for preset in SUBDIVISIONS {
+                    ui.selectable_value(subdivision, preset, subdivision_label(preset));
+                }
+            });
+        ui.add_sized(
+            egui::vec2(drag_width, ui.spacing().interact_size.y),
+            egui::DragValue::new(subdivision).range(1..=64),
+        )
+        .on_hover_text(tr("1拍あたりの分割数"));
+    });
+}
+
+fn tempo_ramp_label(tempo_ramp: crate::grid::TempoRamp) -> String {
+    match tempo_ramp {
+        crate::grid::TempoRamp::None => tr("切り替え"),
+        crate::grid::TempoRamp::Linear => tr("直線的に変化"),
+        crate::grid::TempoRamp::Exponential => tr("指数的に変化"),
+    }
+}
+
+fn tolerance_unit_label(unit: crate::find::ToleranceUnit) -> String {
+    match unit {
+        crate::find::ToleranceUnit::Frames => tr("フレーム"),
+        crate::find::ToleranceUnit::Milliseconds => tr("ミリ秒"),
+        crate::find::ToleranceUnit::Beats => tr("拍"),
+    }
+}
+
+fn rounding_label(rounding: crate::grid::FrameRounding) -> String {
+    match rounding {
+        crate::grid::FrameRounding::Ceil => tr("拍の後のフレーム"),
+        crate::grid::FrameRounding::Round => tr("拍に一番近いフレーム"),
+        crate::grid::FrameRounding::Floor => tr("拍を含むフレーム"),
+    }
+}
+
+fn note_value_label(note: crate::grid::NoteValue) -> String {
+    match note {
+        crate::grid::NoteValue::Beat => tr("1拍"),
+        crate::grid::NoteValue::HalfBeat => tr("1/2拍"),
+        crate::grid::NoteValue::Bar => tr("1小節"),
+    }
+}
+
+fn snap_direction_label(direction: crate::grid::SnapDirection) -> String {
+    match direction {
+        crate::grid::SnapDirection::Nearest => tr("近い方へ"),
+        crate::grid::SnapDirection::Earlier => tr("前へのみ"),
+        crate::grid::SnapDirection::Later => tr("後ろへのみ"),
+    }
+}
+
+/// 開始位置・中継点・終了位置ごとの設定
+struct TimingSetting {
+    direction: crate::grid::SnapDirection,
+    // NOTE: 単位を切り替えても値が残るように、単位ごとに持つ
+    frames: usize,
+    milliseconds: f64,
+    beats: f64,
+}
+
+impl Default for TimingSetting {
+    fn default() -> Self {
+        Self {
+            direction: crate::grid::SnapDirection::Nearest,
+            frames: 1,
+            milliseconds: 20.0,
+            beats: 0.125,
+        }
+    }
+}
+
+impl TimingSetting {
+    fn tolerance(&self, unit: crate::find::ToleranceUnit) -> crate::find::Tolerance {
+        crate::find::Tolerance {
+            value: match unit {
+                crate::find::ToleranceUnit::Frames => self.frames as f64,
+                crate::find::ToleranceUnit::Milliseconds => self.milliseconds,
+                crate::find::ToleranceUnit::Beats => self.beats,
+            },
+            unit,
+        }
+    }
+
+    fn tolerance_drag_value(
+        &mut self,
+        unit: crate::find::ToleranceUnit,
+        max_tolerance: f64,
+    ) -> egui::DragValue<'_> {
+        match unit {
+            crate::find::ToleranceUnit::Frames => egui::DragValue::new(&mut self.frames)
+                .range(1..=(max_tolerance.floor() as i32).max(1))
+                .suffix("f"),
+            crate::find::ToleranceUnit::Milliseconds => {
+                egui::DragValue::new(&mut self.milliseconds)
+                    .range(1.0..=max_tolerance.max(1.0))
+                    .fixed_decimals(1)
+                    .suffix("ms")
+            }
+            crate::find::ToleranceUnit::Beats => egui::DragValue::new(&mut self.beats)
+                .range(0.001..=max_tolerance.max(0.001))
+                .speed(0.001)
+                .fixed_decimals(3),
+        }
+    }
+}
+
+fn target_row(
+    ui: &mut egui::Ui,
+    id: &str,
+    enabled: &mut bool,
+    setting: &mut TimingSetting,
+    unit: crate::find::ToleranceUnit,
+    max_tolerance: f64,
+    label: &str,
+) {
+    ui.horizontal(|ui| {
+        ui.checkbox(enabled, label);
+        ui.add_enabled_ui(*enabled, |ui| {
+            egui::ComboBox::from_id_salt(id)
+                .selected_text(snap_direction_label(setting.direction))
+                .show_ui(ui, |ui| {
+                    for candidate in [
+                        crate::grid::SnapDirection::Nearest,
+                        crate::grid::SnapDirection::Earlier,
+                        crate::grid::SnapDirection::Later,
+                    ] {
+                        ui.selectable_value(
+                            &mut setting.direction,
+                            candidate,
+                            snap_direction_label(candidate),
+                        );
+                    }
+                })
+                .response
+                .on_hover_text(tr("補正でどちらに動かしてよいか"));
+            ui.add_sized(
+                egui::vec2(ui.available_width(), ui.spacing().interact_size.y),
+                setting.tolerance_drag_value(unit, max_tolerance),
+            )
+            .on_hover_text(tr("これ以上ずれているものはズレとみなさない"));
+        });
+    });
+}
+
+/// 移動ごとの（オブジェクト名, 種別, 移動）
+fn planned_move_rows(
+    plan: &crate::find::FixPlan,
+) -> Vec<(String, String, &crate::find::PlannedMove)> {
+    plan.moves
+        .iter()
+        .map(|planned_move| {
+            let (name, kind) = match &plan.offbeat_info.timing_type {
+                crate::find::TimingType::EndThenStart {
+                    object_name_left,
+                    object_name_right,
+                    ..
+                } => {
+                    if planned_move.object == plan.offbeat_info.object {
+                        (object_name_right.clone(), tr("開始位置"))
+                    } else {
+                        (object_name_left.clone(), tr("終了位置"))
+                    }
+                }
+                timing_type => timing_name_kind(timing_type),
+            };
+            let object_sections = plan
+                .moves
+                .iter()
+                .filter(|other| other.object == planned_move.object)
+                .map(|other| other.section);
+            // NOTE: 伸縮する場合は、区間の番号から種別を決める
+            let kind = match object_sections.clone().max() {
+                _ if planned_move.whole_object => tr("オブジェクト全体"),
+                Some(last_section) if object_sections.count() > 1 => {
+                    if planned_move.section == 0 {
+                        tr("開始位置")
+                    } else if planned_move.section == last_section {
+                        tr("終了位置")
+                    } else {
+                        let index = planned_move.section.to_string();
+                        tr_format("中継点（{index}）", &[("index", &index)])
+                    }
+                }
+                _ => kind,
+            };
+            (name, kind, planned_move)
+        })
+        .collect()
+}
+
+/// 補正内容をタブ区切りのテキストにする
+fn fix_review_text(rows: &[FixReviewRow]) -> String {
+    let mut text = [
+        tr("補正"),
+        tr("レイヤー"),
+        tr("オブジェクト"),
+        tr("種別"),
+        tr("変更前"),
+        tr("変更後"),
+    ]
+    .join("\t");
+    text.push('\n');
+    for row in rows {
+        for (name, kind, planned_move) in planned_move_rows(&row.plan) {
+            text.push_str(
+                &[
+                    if row.accepted { "o" } else { "x" }.to_string(),
+                    row.plan.offbeat_info.layer_name.clone(),
+                    name,
+                    kind,
+                    planned_move.old_frame.to_string(),
+                    planned_move.new_frame.to_string(),
+                ]
+                .join("\t"),
+            );
+            text.push('\n');
+        }
+    }
+    text
+}
+
+/// 補正内容の表。checkableのときは補正するかを選べる
+fn fix_rows_grid(ui: &mut egui::Ui, id: &str, rows: &mut [FixReviewRow], checkable: bool) {
+    egui::Grid::new(id)
+        .striped(true)
+        .num_columns(6)
+        .show(ui, |ui| {
+            ui.label("");
+            ui.strong(tr("レイヤー"));
+            ui.strong(tr("オブジェクト"));
+            ui.strong(tr("種別"));
+            ui.strong(tr("変更前"));
+            ui.strong(tr("変更後"));
+            ui.end_row();
+            for row in rows.iter_mut() {
+                for (i, (name, kind, planned_move)) in
+                    planned_move_rows(&row.plan).into_iter().enumerate()
+                {
+                    if i == 0 {
+                        if checkable {
+                            ui.checkbox(&mut row.accepted, "");
+                        } else {
+                            ui.label("");
+                        }
+                        ui.label(&row.plan.offbeat_info.layer_name);
+                    } else {
+                        ui.label("");
+                        ui.label("");
+                    }
+                    ui.label(name);
+                    ui.label(kind);
+                    ui.label(format!("{}f", planned_move.old_frame));
+                    ui.label(format!("{}f", planned_move.new_frame));
+                    ui.end_row();
+                }
+            }
+        });
+}
+
+fn file_name(path: &std::path::Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// （オブジェクト名, 種別）
+fn timing_name_kind(timing_type: &crate::find::TimingType) -> (String, String) {
+    match timing_type {
+        crate::find::TimingType::Start { object_name } => (object_name.clone(), tr("開始位置")),
+        crate::find::TimingType::Keyframe {
+            object_name,
+            keyframe_index,
+        } => {
+            let index = (keyframe_index + 1).to_string();
+            (
+                object_name.clone(),
+                tr_format("中継点（{index}）", &[("index", &index)]),
+            )
+        }
+        crate::find::TimingType::End { object_name } => (object_name.clone(), tr("終了位置")),
+        crate::find::TimingType::EndThenStart {
+            object_name_right, ..
+        } => (object_name_right.clone(), tr("境界")),
+    }
+}
+
+fn blocked_reason(blocked: &crate::find::Blocked) -> String {
+    let crate::find::Blocked::Neighbour {
+        side, neighbour, ..
+    } = blocked
+    else {
+        return tr("開始位置と終了位置の向きの制約が両立しません");
+    };
+    let (name, kind) = timing_name_kind(&neighbour.timing_type);
+    let frame = neighbour.frame.to_string();
+    let template = match side {
+        crate::find::NeighbourSide::Previous => "前の{name}（{kind}・{frame}f）とぶつかります",
+        crate::find::NeighbourSide::Next => "後ろの{name}（{kind}・{frame}f）とぶつかります",
+    };
+    tr_format(
+        template,
+        &[("name", &name), ("kind", &kind), ("frame", &frame)],
+    )
+}
+
+/// ズレのカードで押されたボタン
+enum GapCardAction {
+    /// 補正内容を確認してから補正する。隣とぶつかるものは補正のしかたを指定する
+    Fix(Option<crate::find::BlockResolution>),
+    /// まとめて補正するチェーンの先頭のオブジェクト
+    FixChain(aviutl2::generic::ObjectHandle),
+    /// 一覧から外す（除外・スキップ）
+    Remove,
+}
+
+fn full_width_button(ui: &mut egui::Ui, label: &str) -> egui::Response {
+    ui.add_sized(
+        egui::vec2(ui.available_width(), ui.spacing().interact_size.y),
+        egui::Button::new(label),
+    )
+    .on_hover_cursor(egui::CursorIcon::PointingHand)
+}
+
+fn warn_label(ui: &mut egui::Ui, text: &str) {
+    ui.scope(|ui| {
+        ui.visuals_mut().override_text_color = Some(ui.visuals().warn_fg_color);
+        ui.label(text);
+    });
+}
+
 fn label_truncated(ui: &mut egui::Ui, text: String) {
     ui.add(egui::Label::new(&text).truncate())
         .on_hover_text(text);
@@ -58,16 +486,167 @@ impl QuantizerGuiApp {
             suppress_info_close_once: false,
             header_collapsed,
             version: env!("CARGO_PKG_VERSION").to_string(),
-            frame_count: 1,
+            tolerance_unit: crate::find::ToleranceUnit::Frames,
+            grid_source_kind: GridSourceKind::Project,
+            tempo_map: None,
+            onsets: None,
+            onset_loading: None,
+            audio_start_frame: 0,
+            bpm_estimate: None,
+            offset_fit: None,
+            offset_fitting: None,
+            bpm_grid_confirm: None,
+            subdivision: 1,
+            mix_subdivision: false,
+            mixed_subdivision: 3,
+            swing_percent: 50.0,
+            snap_to_bar: false,
+            tempo_ramp: crate::grid::TempoRamp::None,
+            rounding: crate::grid::FrameRounding::Ceil,
             target_start: true,
             target_middle: true,
             target_end: true,
             target_project_end: false,
+            target_downbeat_only: false,
+            min_offset_frames: 1,
+            start_move: crate::find::StartMove::default(),
+            stretch_keyframes: false,
+            join_gaps: false,
+            length_mode: false,
+            length_note: crate::grid::NoteValue::default(),
+            target_start_setting: TimingSetting::default(),
+            target_middle_setting: TimingSetting::default(),
+            target_end_setting: TimingSetting::default(),
             sort_by: SortBy::Frame,
             auto_jump: true,
             selected_gap_index: 0,
+            fix_failures: Vec::new(),
+            fix_review: None,
+            applied_fixes: Vec::new(),
+            fix_history: Vec::new(),
+            fix_context: None,
             gaps: None,
+            blocked_gaps: Vec::new(),
+        }
+    }
+
+    fn grid_config(&self) -> crate::grid::GridConfig {
+        let mut subdivisions = vec![self.subdivision];
+        if self.mix_subdivision && self.mixed_subdivision != self.subdivision {
+            subdivisions.push(self.mixed_subdivision);
+        }
+        crate::grid::GridConfig {
+            subdivisions,
+            swing: self.swing_percent / 100.0,
+            snap_to_bar: self.snap_to_bar,
+            tempo_ramp: self.tempo_ramp,
+            rounding: self.rounding,
+        }
+    }
+
+    fn find_target(&self) -> crate::find::FindTarget {
+        crate::find::FindTarget {
+            start: self.target_start,
+            keyframe: self.target_middle,
+            end: self.target_end,
+            project_end: self.target_project_end,
+            downbeat_only: self.target_downbeat_only,
+            start_direction: self.target_start_setting.direction,
+            keyframe_direction: self.target_middle_setting.direction,
+            end_direction: self.target_end_setting.direction,
+            start_tolerance: self.target_start_setting.tolerance(self.tolerance_unit),
+            keyframe_tolerance: self.target_middle_setting.tolerance(self.tolerance_unit),
+            end_tolerance: self.target_end_setting.tolerance(self.tolerance_unit),
+            min_offset_frames: self.min_offset_frames,
+            start_move: self.start_move,
+            stretch_keyframes: self.stretch_keyframes,
+            join_gaps: self.join_gaps,
+            // NOTE: 長さは拍で数えるので、BPMグリッドのときだけ
+            length_note: (self.length_mode && self.grid_source_kind != GridSourceKind::Audio)
+                .then_some(self.length_note),
+        }
+    }
+
+    fn grid_source(&self) -> Option<crate::find::GridSource> {
+        match self.grid_source_kind {
+            GridSourceKind::Project => Some(crate::find::GridSource::Project),
+            GridSourceKind::TempoMap => self
+                .tempo_map
+                .as_ref()
+                .map(|(_, bpm_list)| crate::find::GridSource::TempoMap(bpm_list.clone())),
+            GridSourceKind::Audio => {
+                self.onsets
+                    .as_ref()
+                    .map(|(_, onsets)| crate::find::GridSource::Onsets {
+                        onsets: onsets.clone(),
+                        start_frame: self.audio_start_frame,
+                    })
+            }
+        }
+    }
+
+    fn load_tempo_map(&mut self) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter(tr("テンポマップ"), &crate::tempo_map::SUPPORTED_EXTENSIONS)
+            .pick_file()
+        else {
+            return;
+        };
+        match crate::tempo_map::read_tempo_map_file(&path) {
+            Ok(bpm_list) => {
+                tracing::info!(
+                    "Loaded {} BPM entries from {}",
+                    bpm_list.len(),
+                    path.display()
+                );
+                self.tempo_map = Some((path, bpm_list));
+            }
+            Err(e) => {
+                tracing::error!("Failed to load tempo map: {e}");
+            }
+        }
+    }
+
+    fn load_onsets(&mut self) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter(tr("音声ファイル"), &crate::onset::SUPPORTED_EXTENSIONS)
+            .pick_file()
+        else {
+            return;
+        };
+        // NOTE: 長い音声の解析は時間がかかるので、GUIを止めないように別スレッドで行う
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let thread_path = path.clone();
+        std::thread::spawn(move || {
+            let _ = sender.send(crate::onset::detect_onsets_in_file(&thread_path));
+        });
+        self.onset_loading = Some((path, receiver));
+    }
+
+    fn poll_onsets(&mut self, ctx: &egui::Context) {
+        let Some((path, receiver)) = &self.onset_loading else {
+            return;
+        };
+        let result = match receiver.try_recv() {
+            Ok(result) => result,
+            Err(std::sync::mpsc::TryRecvError::Empty) => {
+                ctx.request_repaint_after(std::time::Duration::from_millis(100));
+                return;
+            }
+            Err(std::sync::mpsc::TryRecvError::Disconnected) => {
+                Err(anyhow::anyhow!("Onset detection thread stopped"))
+            }
+        };
+        match result {
+            Ok(onsets) => {
+                tracing::info!("Detected {} onsets in {}", onsets.len(), path.display());
+                self.onsets = Some((path.clone(), onsets.into()));
+            }
+            Err(e) => {
+                tracing::error!("Failed to detect onsets: {e}");
+            }
         }
+        self.onset_loading = None;
     }
 
     fn render_header(&mut self, ui: &mut egui::Ui) {
@@ -132,7 +711,9 @@ impl QuantizerGuiApp {
     }
 
     fn render_main_panel(&mut self, ui: &mut egui::Ui) {
-        if self.gaps.is_some() {
+        if self.bpm_grid_confirm.is_some() {
+            self.render_bpm_grid_confirm(ui);
+        } else if self.gaps.is_some() {
             self.render_gaps_panel(ui);
         } else {
             self.render_find_panel(ui);
@@ -141,29 +722,45 @@ impl QuantizerGuiApp {
 
     fn render_find_panel(&mut self, ui: &mut egui::Ui) {
         egui::CentralPanel::default().show(ui, |ui| {
+            let grid_source = self.grid_source();
             let response = ui
-                .add_sized(
-                    egui::vec2(ui.available_width(), 40.0),
-                    egui::Button::new(tr("ズレを検出")),
-                )
+                .add_enabled_ui(grid_source.is_some(), |ui| {
+                    ui.add_sized(
+                        egui::vec2(ui.available_width(), 40.0),
+                        egui::Button::new(tr("ズレを検出")),
+                    )
+                })
+                .inner
                 .on_hover_cursor(egui::CursorIcon::PointingHand);
-            if response.clicked() {
-                let find_target = crate::find::FindTarget {
-                    start: self.target_start,
-                    keyframe: self.target_middle,
-                    end: self.target_end,
-                    project_end: self.target_project_end,
+            if response.clicked()
+                && let Some(grid_source) = grid_source
+            {
+                let context = crate::find::FixContext {
+                    find_target: self.find_target(),
+                    grid_source,
+                    grid_config: self.grid_config(),
                 };
-                match crate::find::find_offsync_objects(&find_target, self.frame_count) {
-                    Ok(mut gaps) => {
-                        tracing::info!("Found {} off-sync objects", gaps.len());
-                        gaps.sort_by_key(if self.sort_by == SortBy::Layer {
-                            |gap: &crate::find::OffbeatInfo| (gap.position.layer, gap.frame)
-                        } else {
-                            |gap: &crate::find::OffbeatInfo| (gap.frame, gap.position.layer)
-                        });
+                match crate::find::find_offsync_objects(
+                    &context.find_target,
+                    &context.grid_source,
+                    &context.grid_config,
+                ) {
+                    Ok(gaps) => {
+                        let (blocked_gaps, mut gaps): (Vec<_>, Vec<_>) =
+                            gaps.into_iter().partition(|gap| gap.blocked.is_some());
+                        tracing::info!(
+                            "Found {} off-sync objects, {} blocked",
+                            gaps.len(),
+                            blocked_gaps.len()
+                        );
+                        gaps.sort_by_key(self.sort_by.key());
                         self.selected_gap_index = 0;
                         self.gaps = Some(gaps);
+                        self.blocked_gaps = blocked_gaps;
+                        self.fix_context = Some(context);
+                        self.fix_failures.clear();
+                        self.fix_history.clear();
+                        self.applied_fixes.clear();
                     }
                     Err(e) => {
                         tracing::error!("Failed to find off-sync objects: {e}");
@@ -172,29 +769,557 @@ impl QuantizerGuiApp {
                 }
             }
 
+            self.render_revert_button(ui);
+
             ui.add_space(8.0);
-            ui.label(tr("フレーム数："));
-            let max_frames = match crate::find::max_frames_per_beat() {
-                Ok(max_frames) => max_frames,
+            self.render_grid_source(ui);
+
+            ui.add_space(8.0);
+            let uses_bpm_grid = self.grid_source_kind != GridSourceKind::Audio;
+            ui.label(tr("グリッド："));
+            ui.add_enabled(
+                uses_bpm_grid,
+                egui::Checkbox::new(&mut self.snap_to_bar, tr("小節線にスナップ")),
+            );
+            ui.add_enabled_ui(uses_bpm_grid && !self.snap_to_bar, |ui| {
+                subdivision_selector(ui, "subdivision", &mut self.subdivision);
+                ui.checkbox(&mut self.mix_subdivision, tr("別のグリッドを併用"));
+                if self.mix_subdivision {
+                    subdivision_selector(ui, "mixed_subdivision", &mut self.mixed_subdivision);
+                }
+                ui.label(tr("スウィング："));
+                ui.add(
+                    egui::Slider::new(&mut self.swing_percent, 50.0..=75.0)
+                        .suffix("%")
+                        .fixed_decimals(1),
+                )
+                .on_hover_text(tr(
+                    "50%でストレート、66.7%で3連符のスウィングになります。3連符など奇数の分割には効きません。",
+                ));
+            });
+            ui.add_enabled_ui(uses_bpm_grid, |ui| {
+                ui.label(tr("BPMの変化："));
+                egui::ComboBox::from_id_salt("tempo_ramp")
+                    .width(ui.available_width())
+                    .selected_text(tempo_ramp_label(self.tempo_ramp))
+                    .show_ui(ui, |ui| {
+                        for tempo_ramp in [
+                            crate::grid::TempoRamp::None,
+                            crate::grid::TempoRamp::Linear,
+                            crate::grid::TempoRamp::Exponential,
+                        ] {
+                            ui.selectable_value(
+                                &mut self.tempo_ramp,
+                                tempo_ramp,
+                                tempo_ramp_label(tempo_ramp),
+                            );
+                        }
+                    });
+            });
+            ui.label(tr("フレームの丸め："));
+            egui::ComboBox::from_id_salt("rounding")
+                .width(ui.available_width())
+                .selected_text(rounding_label(self.rounding))
+                .show_ui(ui, |ui| {
+                    for rounding in [
+                        crate::grid::FrameRounding::Ceil,
+                        crate::grid::FrameRounding::Round,
+                        crate::grid::FrameRounding::Floor,
+                    ] {
+                        ui.selectable_value(&mut self.rounding, rounding, rounding_label(rounding));
+                    }
+                })
+                .response
+                .on_hover_text(tr(
+                    "拍の瞬間がフレームの間にあるとき、どのフレームをグリッドとみなすか",
+                ));
+
+            ui.add_space(8.0);
+            ui.label(tr("許容範囲："));
+            let Some(grid_source) = self.grid_source() else {
+                return;
+            };
+            let max_tolerance = match crate::find::max_tolerance(
+                &grid_source,
+                &self.grid_config(),
+                self.tolerance_unit,
+            ) {
+                Ok(max_tolerance) => max_tolerance,
                 Err(e) => {
-                    tracing::error!("Failed to calculate max frames per beat: {e}");
+                    tracing::error!("Failed to calculate max tolerance: {e}");
                     return;
                 }
             };
-            ui.add_sized(
-                egui::vec2(ui.available_width(), ui.spacing().interact_size.y),
-                egui::DragValue::new(&mut self.frame_count)
-                    .range(1..=((max_frames / 2.0).floor() as i32)),
-            );
+            egui::ComboBox::from_id_salt("tolerance_unit")
+                .width(ui.available_width())
+                .selected_text(tolerance_unit_label(self.tolerance_unit))
+                .show_ui(ui, |ui| {
+                    for unit in [
+                        crate::find::ToleranceUnit::Frames,
+                        crate::find::ToleranceUnit::Milliseconds,
+                        crate::find::ToleranceUnit::Beats,
+                    ] {
+                        ui.selectable_value(
+                            &mut self.tolerance_unit,
+                            unit,
+                            tolerance_unit_label(unit),
+                        );
+                    }
+                });
+
+            ui.horizontal(|ui| {
+                ui.label(tr("最小のズレ："));
+                ui.add_sized(
+                    egui::vec2(ui.available_width(), ui.spacing().interact_size.y),
+                    egui::DragValue::new(&mut self.min_offset_frames)
+                        .range(1..=u64::MAX)
+                        .suffix("f"),
+                )
+                .on_hover_text(tr(
+                    "これより小さいズレは無視します。高フレームレートのプロジェクトで1フレームのズレを無視したいときに使います。",
+                ));
+            });
 
             ui.add_space(8.0);
+            ui.add_enabled_ui(uses_bpm_grid, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label(tr("見るもの："));
+                    ui.selectable_value(&mut self.length_mode, false, tr("位置"));
+                    ui.selectable_value(&mut self.length_mode, true, tr("長さ"))
+                        .on_hover_text(tr(
+                            "オブジェクトの長さが音価の整数倍になっていないものを探し、終了位置だけを補正します。向きと許容範囲は終了位置の設定を使い、終了位置を対象にしていないときは探しません。",
+                        ));
+                    if self.length_mode {
+                        egui::ComboBox::from_id_salt("length_note")
+                            .width(ui.available_width())
+                            .selected_text(note_value_label(self.length_note))
+                            .show_ui(ui, |ui| {
+                                for note in [
+                                    crate::grid::NoteValue::Beat,
+                                    crate::grid::NoteValue::HalfBeat,
+                                    crate::grid::NoteValue::Bar,
+                                ] {
+                                    ui.selectable_value(
+                                        &mut self.length_note,
+                                        note,
+                                        note_value_label(note),
+                                    );
+                                }
+                            });
+                    }
+                });
+            });
             ui.vertical(|ui| {
                 ui.label(tr("対象："));
-                ui.checkbox(&mut self.target_start, tr("開始位置"));
-                ui.checkbox(&mut self.target_middle, tr("中継点"));
-                ui.checkbox(&mut self.target_end, tr("終了位置"));
+                target_row(
+                    ui,
+                    "target_start_setting",
+                    &mut self.target_start,
+                    &mut self.target_start_setting,
+                    self.tolerance_unit,
+                    max_tolerance,
+                    &tr("開始位置"),
+                );
+                ui.add_enabled_ui(self.target_start && !self.stretch_keyframes, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label(tr("開始位置の動かし方："));
+                        ui.selectable_value(
+                            &mut self.start_move,
+                            crate::find::StartMove::Section,
+                            tr("開始位置だけ"),
+                        );
+                        ui.selectable_value(
+                            &mut self.start_move,
+                            crate::find::StartMove::Object,
+                            tr("オブジェクトごと"),
+                        )
+                        .on_hover_text(tr(
+                            "中間点ごとオブジェクト全体を動かし、長さを変えずに開始位置をグリッドに合わせます。後ろにつながっているオブジェクトも一緒にずらします。",
+                        ));
+                    });
+                });
+                target_row(
+                    ui,
+                    "target_middle_setting",
+                    &mut self.target_middle,
+                    &mut self.target_middle_setting,
+                    self.tolerance_unit,
+                    max_tolerance,
+                    &tr("中継点"),
+                );
+                target_row(
+                    ui,
+                    "target_end_setting",
+                    &mut self.target_end,
+                    &mut self.target_end_setting,
+                    self.tolerance_unit,
+                    max_tolerance,
+                    &tr("終了位置"),
+                );
+                ui.add_enabled(
+                    self.target_start || self.target_end,
+                    egui::Checkbox::new(
+                        &mut self.stretch_keyframes,
+                        tr("中間点を伸縮する"),
+                    ),
+                )
+                .on_hover_text(tr(
+                    "開始位置・終了位置を合わせるときに、オブジェクトの開始位置と終了位置をまとめて動かし、中間点を新しい長さに合わせて比例で動かします。",
+                ));
+                ui.add_enabled(
+                    self.target_start || self.target_end,
+                    egui::Checkbox::new(&mut self.join_gaps, tr("1〜2フレームの隙間を詰める")),
+                )
+                .on_hover_text(tr(
+                    "隣のオブジェクトとの間に1〜2フレームの隙間しかないときも、つながっている境界として探し、補正で隙間を詰めます。",
+                ));
                 ui.checkbox(&mut self.target_project_end, tr("プロジェクト終端"));
+                ui.add_enabled(
+                    uses_bpm_grid && !self.snap_to_bar && self.target_start,
+                    egui::Checkbox::new(&mut self.target_downbeat_only, tr("小節線付近のみ")),
+                )
+                .on_hover_text(tr(
+                    "開始位置は、小節線の近くにあるものだけを探します。中継点と終了位置には影響しません。",
+                ));
+            });
+
+            ui.add_space(8.0);
+            // NOTE: 長さはオフセットをずらしても変わらないので、長さを合わせるときは使えない
+            ui.add_enabled_ui(
+                uses_bpm_grid && !self.length_mode && self.offset_fitting.is_none(),
+                |ui| {
+                    if full_width_button(ui, &tr("グリッドのオフセットを合わせる"))
+                        .on_hover_text(tr(
+                            "テンポはそのままに、ズレが最も小さくなるようにグリッドをずらします。",
+                        ))
+                        .clicked()
+                    {
+                        self.fit_grid_offset(&grid_source);
+                    }
+                },
+            );
+            if self.offset_fitting.is_some() {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label(tr("オフセットを合わせています..."));
+                });
+            }
+            self.render_offset_fit(ui);
+        });
+    }
+
+    fn fit_grid_offset(&mut self, grid_source: &crate::find::GridSource) {
+        let job = match crate::find::prepare_grid_offset_fit(
+            &self.find_target(),
+            grid_source,
+            &self.grid_config(),
+        ) {
+            Ok(job) => job,
+            Err(e) => {
+                tracing::error!("Failed to fit grid offset: {e}");
+                return;
+            }
+        };
+        // NOTE: 長いテンポマップだと時間がかかるので、GUIを止めないように別スレッドで行う
+        let (sender, receiver) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let _ = sender.send(job.run());
+        });
+        self.offset_fit = None;
+        self.offset_fitting = Some((self.grid_source_kind, receiver));
+    }
+
+    fn poll_offset_fit(&mut self, ctx: &egui::Context) {
+        let Some((kind, receiver)) = &self.offset_fitting else {
+            return;
+        };
+        let result = match receiver.try_recv() {
+            Ok(result) => result,
+            Err(std::sync::mpsc::TryRecvError::Empty) => {
+                ctx.request_repaint_after(std::time::Duration::from_millis(100));
+                return;
+            }
+            Err(std::sync::mpsc::TryRecvError::Disconnected) => {
+                Err(anyhow::anyhow!("Grid offset fitting thread stopped"))
+            }
+        };
+        match result {
+            Ok(fit) => {
+                tracing::info!(
+                    "Fitted grid offset: {} -> {} off-sync objects",
+                    fit.before_count,
+                    fit.after_count
+                );
+                self.offset_fit = Some((*kind, fit));
+            }
+            Err(e) => {
+                tracing::error!("Failed to fit grid offset: {e}");
+            }
+        }
+        self.offset_fitting = None;
+    }
+
+    fn render_offset_fit(&mut self, ui: &mut egui::Ui) {
+        let Some((kind, fit)) = &self.offset_fit else {
+            return;
+        };
+        if *kind != self.grid_source_kind {
+            return;
+        }
+        let before = fit.before_count.to_string();
+        let after = fit.after_count.to_string();
+        ui.label(tr_format(
+            "ズレ：{before}件 → {after}件",
+            &[("before", &before), ("after", &after)],
+        ));
+        if !full_width_button(ui, &tr("オフセットを適用")).clicked() {
+            return;
+        }
+        let bpm_list = fit.bpm_list.clone();
+        match self.grid_source_kind {
+            // NOTE: オフセットの結果は、書き込んだときに消える
+            GridSourceKind::Project => {
+                self.confirm_bpm_grid(bpm_list);
+                return;
+            }
+            GridSourceKind::TempoMap => {
+                if let Some((_, tempo_map)) = &mut self.tempo_map {
+                    *tempo_map = bpm_list;
+                }
+            }
+            // NOTE: 音声ファイルのグリッドにはオフセットがないので、合わせることもない
+            GridSourceKind::Audio => return,
+        }
+        self.offset_fit = None;
+    }
+
+    fn render_grid_source(&mut self, ui: &mut egui::Ui) {
+        ui.label(tr("グリッドの取得元："));
+        ui.horizontal_wrapped(|ui| {
+            ui.radio_value(
+                &mut self.grid_source_kind,
+                GridSourceKind::Project,
+                tr("プロジェクト"),
+            );
+            ui.radio_value(
+                &mut self.grid_source_kind,
+                GridSourceKind::TempoMap,
+                tr("テンポマップ"),
+            );
+            ui.radio_value(
+                &mut self.grid_source_kind,
+                GridSourceKind::Audio,
+                tr("音声ファイル"),
+            );
+        });
+        match self.grid_source_kind {
+            GridSourceKind::Project => {
+                if full_width_button(ui, &tr("オブジェクトの境界からBPMを推定")).clicked()
+                {
+                    match crate::find::estimate_bpm_grid(&self.grid_config()) {
+                        Ok(estimate) => {
+                            tracing::info!("Estimated BPM grid: {estimate:?}");
+                            self.bpm_estimate = Some(estimate);
+                        }
+                        Err(e) => {
+                            tracing::error!("Failed to estimate BPM grid: {e}");
+                        }
+                    }
+                }
+                if let Some(estimate) = self.bpm_estimate {
+                    let tempo = format!("{:.2}", estimate.tempo);
+                    let offset = format!("{:.3}", estimate.offset);
+                    let score = format!("{:.0}", estimate.score * 100.0);
+                    label_truncated(
+                        ui,
+                        tr_format(
+                            "推定：{tempo}BPM、オフセット{offset}秒（一致度：{score}%）",
+                            &[("tempo", &tempo), ("offset", &offset), ("score", &score)],
+                        ),
+                    );
+                    if full_width_button(ui, &tr("プロジェクトのBPMグリッドに書き込む")).clicked()
+                    {
+                        // NOTE: 拍子は今のプロジェクトのものを引き継ぐ
+                        match crate::find::read_grid_bpm_list() {
+                            Ok(current) => {
+                                let beat = current.first().map_or(4, |bpm| bpm.beat);
+                                self.bpm_grid_confirm = Some(BpmGridConfirm {
+                                    current,
+                                    new: vec![estimate.to_bpm_info(beat)],
+                                });
+                            }
+                            Err(e) => {
+                                tracing::error!("Failed to read BPM grid: {e}");
+                            }
+                        }
+                    }
+                }
+            }
+            GridSourceKind::TempoMap => {
+                if full_width_button(ui, &tr("テンポマップを開く...")).clicked() {
+                    self.load_tempo_map();
+                }
+                match &self.tempo_map {
+                    Some((path, bpm_list)) => {
+                        let name = file_name(path);
+                        let count = bpm_list.len().to_string();
+                        label_truncated(
+                            ui,
+                            tr_format(
+                                "{name}（BPM情報：{count}個）",
+                                &[("name", &name), ("count", &count)],
+                            ),
+                        );
+                        if full_width_button(ui, &tr("プロジェクトのBPMグリッドに書き込む"))
+                            .clicked()
+                        {
+                            self.confirm_bpm_grid(bpm_list.clone());
+                        }
+                    }
+                    None => {
+                        warn_label(ui, &tr("テンポマップが読み込まれていません。"));
+                    }
+                }
+            }
+            GridSourceKind::Audio => {
+                let open = ui
+                    .add_enabled_ui(self.onset_loading.is_none(), |ui| {
+                        full_width_button(ui, &tr("音声ファイルを開く..."))
+                    })
+                    .inner;
+                if open.clicked() {
+                    self.load_onsets();
+                }
+                if let Some((path, _)) = &self.onset_loading {
+                    let name = file_name(path);
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        label_truncated(ui, tr_format("{name}を解析中...", &[("name", &name)]));
+                    });
+                }
+                match &self.onsets {
+                    Some((path, onsets)) => {
+                        let name = file_name(path);
+                        let count = onsets.len().to_string();
+                        label_truncated(
+                            ui,
+                            tr_format(
+                                "{name}（オンセット：{count}個）",
+                                &[("name", &name), ("count", &count)],
+                            ),
+                        );
+                    }
+                    None => {
+                        if self.onset_loading.is_none() {
+                            warn_label(ui, &tr("音声ファイルが読み込まれていません。"));
+                        }
+                    }
+                }
+                ui.horizontal(|ui| {
+                    ui.label(tr("音声の開始位置："));
+                    ui.add(egui::DragValue::new(&mut self.audio_start_frame).suffix("f"))
+                        .on_hover_text(tr(
+                            "タイムライン上で音声ファイルが始まるフレーム。オンセットはここからの時間として扱います。",
+                        ));
+                    if ui
+                        .button(tr("選択中のオブジェクトから"))
+                        .on_hover_text(tr(
+                            "選択中のオブジェクト（音声ファイルを読み込んだもの）の開始位置を使います。",
+                        ))
+                        .clicked()
+                    {
+                        match crate::find::focused_object_start() {
+                            Ok(Some(start)) => {
+                                self.audio_start_frame = start;
+                            }
+                            Ok(None) => {
+                                tracing::warn!("No object is focused");
+                            }
+                            Err(e) => {
+                                tracing::error!("Failed to read the focused object: {e}");
+                            }
+                        }
+                    }
+                });
+            }
+        }
+    }
+
+    /// 今のBPMグリッドと並べて確認してから書き込む
+    fn confirm_bpm_grid(&mut self, bpm_list: Vec<aviutl2::generic::BpmInfo>) {
+        match crate::find::read_grid_bpm_list() {
+            Ok(current) => {
+                self.bpm_grid_confirm = Some(BpmGridConfirm {
+                    current,
+                    new: bpm_list,
+                });
+            }
+            Err(e) => {
+                tracing::error!("Failed to read BPM grid: {e}");
+            }
+        }
+    }
+
+    fn render_bpm_grid_confirm(&mut self, ui: &mut egui::Ui) {
+        egui::CentralPanel::default().show(ui, |ui| {
+            let confirm = self.bpm_grid_confirm.as_ref().unwrap();
+            ui.label(tr(
+                "プロジェクトのBPMグリッドを、以下の内容で置き換えます。",
+            ));
+            let write = full_width_button(ui, &tr("書き込む")).clicked();
+            let cancel = full_width_button(ui, &tr("キャンセル")).clicked();
+            ui.add_space(4.0);
+            egui::ScrollArea::both().show(ui, |ui| {
+                for (id, heading, bpm_list) in [
+                    (
+                        "current_bpm_grid",
+                        tr("現在のBPMグリッド"),
+                        &confirm.current,
+                    ),
+                    ("new_bpm_grid", tr("新しいBPMグリッド"), &confirm.new),
+                ] {
+                    ui.add_space(4.0);
+                    ui.strong(heading);
+                    if bpm_list.is_empty() {
+                        ui.label(tr("（なし）"));
+                        continue;
+                    }
+                    egui::Grid::new(id)
+                        .striped(true)
+                        .num_columns(4)
+                        .show(ui, |ui| {
+                            ui.strong(tr("テンポ"));
+                            ui.strong(tr("拍子"));
+                            ui.strong(tr("開始"));
+                            ui.strong(tr("オフセット"));
+                            ui.end_row();
+                            for bpm in bpm_list {
+                                ui.label(format!("{:.2}", bpm.tempo));
+                                ui.label(bpm.beat.to_string());
+                                ui.label(format!("{:.3}", bpm.start));
+                                ui.label(format!("{:.3}", bpm.offset));
+                                ui.end_row();
+                            }
+                        });
+                }
             });
+            if write {
+                let confirm = self.bpm_grid_confirm.take().unwrap();
+                match crate::find::write_grid_bpm_list(&confirm.new) {
+                    Ok(_) => {
+                        tracing::info!("Wrote {} BPM entries to the project", confirm.new.len());
+                        // NOTE: 書き込んだ後は、推定やオフセットの結果は古くなる
+                        self.grid_source_kind = GridSourceKind::Project;
+                        self.bpm_estimate = None;
+                        self.offset_fit = None;
+                    }
+                    Err(e) => {
+                        tracing::error!("Failed to write BPM grid: {e}");
+                    }
+                }
+            } else if cancel {
+                self.bpm_grid_confirm = None;
+            }
         });
     }
 
@@ -208,6 +1333,8 @@ impl QuantizerGuiApp {
                 .on_hover_cursor(egui::CursorIcon::PointingHand);
             if return_response.clicked() {
                 self.gaps = None;
+                self.fix_failures.clear();
+                self.fix_review = None;
                 return;
             }
             let gap_count = self.gaps.as_ref().unwrap().len().to_string();
@@ -216,16 +1343,19 @@ impl QuantizerGuiApp {
                 &[("count", &gap_count)],
             ));
 
+            if self.fix_review.is_some() {
+                self.render_fix_review(ui);
+                return;
+            }
+            self.render_blocked_gaps(ui);
+            self.render_applied_fixes(ui);
             if self.gaps.as_ref().unwrap().is_empty() {
                 return;
             }
             ui.add_space(8.0);
-            ui.scope(|ui| {
-                ui.visuals_mut().override_text_color = Some(ui.visuals().warn_fg_color);
-                ui.label(tr(
-                    "手動でオブジェクトを修正した場合は「検出に戻る」を押してください。",
-                ))
-            });
+            ui.label(tr(
+                "一覧は検出した時点のものです。補正するときは今の位置で計算し直します。",
+            ));
             ui.add_space(4.0);
             ui.horizontal(|ui| {
                 ui.label(tr("ソート："));
@@ -234,74 +1364,488 @@ impl QuantizerGuiApp {
                     .clicked()
                 {
                     self.sort_by = SortBy::Layer;
-                    self.gaps
-                        .as_mut()
-                        .unwrap()
-                        .sort_by_key(|gap| (gap.position.layer, gap.frame));
+                    self.gaps.as_mut().unwrap().sort_by_key(self.sort_by.key());
                 }
                 if ui
                     .selectable_label(self.sort_by == SortBy::Frame, tr("フレーム順"))
                     .clicked()
                 {
                     self.sort_by = SortBy::Frame;
-                    self.gaps
-                        .as_mut()
-                        .unwrap()
-                        .sort_by_key(|gap| (gap.frame, gap.position.layer));
+                    self.gaps.as_mut().unwrap().sort_by_key(self.sort_by.key());
                 }
             });
             ui.horizontal(|ui| {
                 ui.checkbox(&mut self.auto_jump, tr("自動で次にジャンプ"));
             });
+            ui.add_space(4.0);
+            if full_width_button(ui, &tr("すべて補正")).clicked() {
+                let indices = (0..self.gaps.as_ref().unwrap().len()).collect();
+                self.fix_gaps(indices);
+            }
+            if full_width_button(ui, &tr("表示範囲内をすべて補正"))
+                .on_hover_text(tr(
+                    "タイムラインに表示されているフレーム・レイヤーにあるものだけを補正します。",
+                ))
+                .clicked()
+            {
+                let info = crate::EDIT_HANDLE.get_edit_info();
+                let indices = self
+                    .gaps
+                    .as_ref()
+                    .unwrap()
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, gap)| {
+                        info.display_frame.contains(&gap.frame)
+                            && info.display_layer.contains(&gap.position.layer)
+                    })
+                    .map(|(i, _)| i)
+                    .collect();
+                self.fix_gaps(indices);
+            }
+            self.render_revert_button(ui);
+            self.render_fix_failures(ui);
+            if self.gaps.as_ref().unwrap().is_empty() {
+                return;
+            }
 
             egui::ScrollArea::vertical().show(ui, |ui| {
-                let mut remove_indices = std::collections::HashSet::new();
-                let mut interacted_indices = Vec::new();
                 let gaps = self.gaps.as_ref().unwrap();
 
                 if self.selected_gap_index >= gaps.len() {
                     self.selected_gap_index = gaps.len() - 1;
                 }
 
-                if ui.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowUp)) {
-                    if self.selected_gap_index > 0 {
-                        self.selected_gap_index -= 1;
-                    }
+                if ui.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowUp))
+                    && self.selected_gap_index > 0
+                {
+                    self.selected_gap_index -= 1;
                 }
-                if ui.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowDown)) {
-                    if self.selected_gap_index + 1 < gaps.len() {
-                        self.selected_gap_index += 1;
-                    }
+                if ui.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowDown))
+                    && self.selected_gap_index + 1 < gaps.len()
+                {
+                    self.selected_gap_index += 1;
                 }
 
+                let mut actions = Vec::new();
                 for (i, gap) in gaps.iter().enumerate() {
-                    if self.draw_gap_card(ui, gap, self.selected_gap_index == i) {
-                        remove_indices.insert(i);
-                        interacted_indices.push(i);
+                    if let Some(action) = self.draw_gap_card(ui, gap, self.selected_gap_index == i)
+                    {
+                        actions.push((crate::find::timing_key(gap), action));
                     }
                 }
 
-                let gaps = self.gaps.as_mut().unwrap();
-                let mut remove_indices: Vec<usize> = remove_indices.into_iter().collect();
-                remove_indices.sort_unstable();
-
-                for i in remove_indices.into_iter().rev() {
-                    gaps.remove(i);
-                    if self.selected_gap_index > i {
-                        self.selected_gap_index -= 1;
+                // NOTE: 補正を確認するときに一覧が変わることがあるので、位置ではなくキーで探し、
+                //       一覧から外すものは最後にまとめて外す
+                let mut remove_keys = std::collections::HashSet::new();
+                for (key, action) in actions {
+                    match action {
+                        GapCardAction::Fix(_) => {
+                            if let Some(i) = self.gap_position(false, key) {
+                                self.fix_gaps(vec![i]);
+                            }
+                        }
+                        GapCardAction::FixChain(chain) => {
+                            let indices = self
+                                .gaps
+                                .as_ref()
+                                .unwrap()
+                                .iter()
+                                .enumerate()
+                                .filter(|(_, gap)| gap.chain == Some(chain))
+                                .map(|(i, _)| i)
+                                .collect();
+                            self.fix_gaps(indices);
+                        }
+                        GapCardAction::Remove => {
+                            remove_keys.insert(key);
+                        }
                     }
                 }
 
-                if self.auto_jump && !interacted_indices.is_empty() {
-                    let next_index = interacted_indices.iter().min().unwrap();
-                    if let Some(next_gap) = self.gaps.as_ref().unwrap().get(*next_index) {
-                        let res = self.jump_to_gap(next_gap);
-                        if let Err(e) = res {
-                            tracing::error!("Failed to jump to next gap: {e}");
-                        }
-                    }
+                if let Some(next_index) = self.remove_gaps(false, &remove_keys) {
+                    self.jump_to_next_gap(next_index);
+                }
+            });
+        });
+    }
+
+    /// 自動で次にジャンプするときに、一覧から外したズレの次にあるものにジャンプする
+    fn jump_to_next_gap(&self, next_index: usize) {
+        if !self.auto_jump {
+            return;
+        }
+        if let Some(next_gap) = self.gaps.as_ref().unwrap().get(next_index) {
+            let res = self.jump_to_gap(next_gap);
+            if let Err(e) = res {
+                tracing::error!("Failed to jump to next gap: {e}");
+            }
+        }
+    }
+
+    fn gap_list_mut(&mut self, blocked: bool) -> &mut Vec<crate::find::OffbeatInfo> {
+        if blocked {
+            &mut self.blocked_gaps
+        } else {
+            self.gaps.as_mut().unwrap()
+        }
+    }
+
+    /// ズレを通常の一覧に加えて並べ直す。選んでいるズレはそのまま選ぶ
+    fn insert_gaps(&mut self, new_gaps: Vec<crate::find::OffbeatInfo>) {
+        if new_gaps.is_empty() {
+            return;
+        }
+        let gaps = self.gaps.as_mut().unwrap();
+        let selected = gaps
+            .get(self.selected_gap_index)
+            .map(crate::find::timing_key);
+        gaps.extend(new_gaps);
+        gaps.sort_by_key(self.sort_by.key());
+        self.selected_gap_index = selected
+            .and_then(|selected| {
+                gaps.iter()
+                    .position(|gap| crate::find::timing_key(gap) == selected)
+            })
+            .unwrap_or(0);
+    }
+
+    fn gap_position(&mut self, blocked: bool, key: crate::find::TimingKey) -> Option<usize> {
+        self.gap_list_mut(blocked)
+            .iter()
+            .position(|gap| crate::find::timing_key(gap) == key)
+    }
+
+    /// キーが一致するズレを一覧から外し、選んでいるズレの位置をずらす。外した最初の位置を返す
+    fn remove_gaps(
+        &mut self,
+        blocked: bool,
+        keys: &std::collections::HashSet<crate::find::TimingKey>,
+    ) -> Option<usize> {
+        let gaps = self.gap_list_mut(blocked);
+        let removed_indices: Vec<usize> = gaps
+            .iter()
+            .enumerate()
+            .filter(|(_, gap)| keys.contains(&crate::find::timing_key(gap)))
+            .map(|(i, _)| i)
+            .collect();
+        gaps.retain(|gap| !keys.contains(&crate::find::timing_key(gap)));
+        if !blocked {
+            self.selected_gap_index -= removed_indices
+                .iter()
+                .filter(|&&i| i < self.selected_gap_index)
+                .count();
+        }
+        removed_indices.first().copied()
+    }
+
+    fn fix_gaps(&mut self, indices: Vec<usize>) {
+        self.review_fixes(false, indices, None);
+    }
+
+    /// 補正する移動を求めて、確認画面を開く
+    fn review_fixes(
+        &mut self,
+        blocked: bool,
+        indices: Vec<usize>,
+        resolution: Option<crate::find::BlockResolution>,
+    ) {
+        let gaps = self.gap_list_mut(blocked);
+        let targets: Vec<crate::find::OffbeatInfo> =
+            indices.iter().map(|&i| gaps[i].clone()).collect();
+        let (plans, failures) =
+            match crate::find::plan_fixes(&targets, self.fix_context.as_ref().unwrap(), resolution)
+            {
+                Ok(result) => result,
+                Err(e) => {
+                    tracing::error!("Failed to plan fixes: {e}");
+                    return;
+                }
+            };
+
+        // NOTE: 検出し直した結果で一覧を更新し、もうズレでなくなったものは一覧から外す
+        let gaps = self.gap_list_mut(blocked);
+        for plan in &plans {
+            gaps[indices[plan.index]] = plan.offbeat_info.clone();
+        }
+        // NOTE: 隣が動いたなどでぶつからなくなったものは、ぶつかるものの一覧から通常の一覧に移す
+        if blocked {
+            let unblocked_keys: std::collections::HashSet<_> = plans
+                .iter()
+                .filter(|plan| plan.offbeat_info.blocked.is_none())
+                .map(|plan| crate::find::timing_key(&plan.offbeat_info))
+                .collect();
+            let unblocked_gaps: Vec<_> = self
+                .blocked_gaps
+                .iter()
+                .filter(|gap| unblocked_keys.contains(&crate::find::timing_key(gap)))
+                .cloned()
+                .collect();
+            self.remove_gaps(true, &unblocked_keys);
+            self.insert_gaps(unblocked_gaps);
+        }
+        let outdated_keys = failures
+            .iter()
+            .filter(|failure| failure.outdated)
+            .map(|failure| crate::find::timing_key(&targets[failure.index]))
+            .collect();
+        self.remove_gaps(blocked, &outdated_keys);
+
+        self.fix_failures = failures
+            .into_iter()
+            .map(|failure| (targets[failure.index].clone(), failure.reason))
+            .collect();
+        if plans.is_empty() {
+            return;
+        }
+        self.fix_review = Some(
+            plans
+                .into_iter()
+                .map(|plan| FixReviewRow {
+                    blocked: blocked && plan.offbeat_info.blocked.is_some(),
+                    covered_keys: plan
+                        .covered
+                        .iter()
+                        .map(|&index| crate::find::timing_key(&targets[index]))
+                        .collect(),
+                    plan,
+                    accepted: true,
+                })
+                .collect(),
+        );
+    }
+
+    fn apply_fix_review(&mut self) {
+        let Some(rows) = self.fix_review.take() else {
+            return;
+        };
+        let rows: Vec<FixReviewRow> = rows.into_iter().filter(|row| row.accepted).collect();
+        let plans: Vec<crate::find::FixPlan> = rows
+            .iter()
+            .enumerate()
+            .map(|(index, row)| crate::find::FixPlan {
+                index,
+                ..row.plan.clone()
+            })
+            .collect();
+        let failures = match crate::find::apply_fix_plans(&plans, &mut self.fix_history) {
+            Ok(failures) => failures,
+            Err(e) => {
+                tracing::error!("Failed to fix gaps: {e}");
+                return;
+            }
+        };
+        tracing::info!(
+            "Fixed {} gaps, {} failed",
+            plans.len() - failures.len(),
+            failures.len()
+        );
+        let failed_indices: std::collections::HashSet<usize> =
+            failures.iter().map(|failure| failure.index).collect();
+        self.fix_failures = failures
+            .into_iter()
+            .map(|failure| (plans[failure.index].offbeat_info.clone(), failure.reason))
+            .collect();
+
+        let fixed_rows: Vec<FixReviewRow> = rows
+            .into_iter()
+            .enumerate()
+            .filter(|(index, _)| !failed_indices.contains(index))
+            .map(|(_, row)| row)
+            .collect();
+        for blocked in [false, true] {
+            let fixed_keys = fixed_rows
+                .iter()
+                .filter(|row| row.blocked == blocked)
+                .flat_map(|row| {
+                    std::iter::once(crate::find::timing_key(&row.plan.offbeat_info))
+                        .chain(row.covered_keys.iter().copied())
+                })
+                .collect();
+            if let Some(next_index) = self.remove_gaps(blocked, &fixed_keys)
+                && !blocked
+            {
+                self.jump_to_next_gap(next_index);
+            }
+        }
+        self.applied_fixes.extend(fixed_rows);
+    }
+
+    fn render_fix_review(&mut self, ui: &mut egui::Ui) {
+        let rows = self.fix_review.as_mut().unwrap();
+        let accepted = rows.iter().filter(|row| row.accepted).count().to_string();
+        let count = rows.len().to_string();
+        ui.add_space(8.0);
+        ui.label(tr_format(
+            "補正内容の確認：{accepted}/{count}件",
+            &[("accepted", &accepted), ("count", &count)],
+        ));
+        ui.horizontal_wrapped(|ui| {
+            if ui.button(tr("すべて選択")).clicked() {
+                rows.iter_mut().for_each(|row| row.accepted = true);
+            }
+            if ui.button(tr("すべて解除")).clicked() {
+                rows.iter_mut().for_each(|row| row.accepted = false);
+            }
+            if ui.button(tr("コピー")).clicked() {
+                ui.ctx().copy_text(fix_review_text(rows));
+            }
+        });
+        let apply = full_width_button(ui, &tr("選択したものを補正"))
+            .on_hover_text(tr(
+                "選択したものを1回の編集で補正します。AviUtl2の「元に戻す」1回でまとめて戻せます。",
+            ))
+            .clicked()
+            || ui.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::Enter));
+        let cancel = full_width_button(ui, &tr("キャンセル")).clicked()
+            || ui.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::Escape));
+        ui.add_space(4.0);
+        egui::ScrollArea::both().show(ui, |ui| {
+            fix_rows_grid(ui, "fix_review", rows, true);
+        });
+        if apply {
+            self.apply_fix_review();
+        } else if cancel {
+            self.fix_review = None;
+        }
+    }
+
+    fn render_applied_fixes(&mut self, ui: &mut egui::Ui) {
+        if self.applied_fixes.is_empty() {
+            return;
+        }
+        let count = self.applied_fixes.len().to_string();
+        egui::CollapsingHeader::new(tr_format("補正した内容：{count}件", &[("count", &count)]))
+            .id_salt("applied_fixes")
+            .show(ui, |ui| {
+                if ui.button(tr("コピー")).clicked() {
+                    ui.ctx().copy_text(fix_review_text(&self.applied_fixes));
                 }
+                egui::ScrollArea::both()
+                    .id_salt("applied_fixes_scroll")
+                    .max_height(240.0)
+                    .show(ui, |ui| {
+                        fix_rows_grid(ui, "applied_fixes_grid", &mut self.applied_fixes, false);
+                    });
             });
+    }
+
+    fn render_revert_button(&mut self, ui: &mut egui::Ui) {
+        if self.fix_history.is_empty() {
+            return;
+        }
+        let count = self.fix_history.len().to_string();
+        let response = full_width_button(
+            ui,
+            &tr_format("検出してからの補正を元に戻す（{count}箇所）", &[("count", &count)]),
+        )
+        .on_hover_text(tr(
+            "検出してから行った補正を、1回の編集でまとめて補正前の位置に戻します。補正の後に動かしたものは戻しません。",
+        ));
+        if !response.clicked() {
+            return;
+        }
+        let moves = std::mem::take(&mut self.fix_history);
+        match crate::find::revert_moves(&moves) {
+            Ok(failures) => {
+                for failure in &failures {
+                    let planned_move = &moves[failure.index];
+                    tracing::error!(
+                        "Failed to revert section {} ({}f -> {}f): {}",
+                        planned_move.section,
+                        planned_move.new_frame,
+                        planned_move.old_frame,
+                        failure.reason
+                    );
+                }
+                tracing::info!(
+                    "Reverted {} moves, {} failed",
+                    moves.len() - failures.len(),
+                    failures.len()
+                );
+            }
+            Err(e) => {
+                tracing::error!("Failed to revert fixes: {e}");
+                self.fix_history = moves;
+                return;
+            }
+        }
+        // NOTE: 戻したズレは一覧から消えているので、検出し直してもらう
+        self.gaps = None;
+        self.fix_failures.clear();
+        self.fix_review = None;
+        self.applied_fixes.clear();
+    }
+
+    fn render_blocked_gaps(&mut self, ui: &mut egui::Ui) {
+        if self.blocked_gaps.is_empty() {
+            return;
+        }
+        let count = self.blocked_gaps.len().to_string();
+        egui::CollapsingHeader::new(tr_format(
+            "隣とぶつかるもの：{count}件",
+            &[("count", &count)],
+        ))
+        .id_salt("blocked_gaps")
+        .show(ui, |ui| {
+            egui::ScrollArea::vertical()
+                .id_salt("blocked_gaps_scroll")
+                .max_height(240.0)
+                .show(ui, |ui| {
+                    let mut actions = Vec::new();
+                    for gap in &self.blocked_gaps {
+                        if let Some(action) = self.draw_gap_card(ui, gap, false) {
+                            actions.push((crate::find::timing_key(gap), action));
+                        }
+                    }
+                    let mut remove_keys = std::collections::HashSet::new();
+                    for (key, action) in actions {
+                        match action {
+                            GapCardAction::Fix(resolution) => {
+                                if let Some(i) = self.gap_position(true, key) {
+                                    self.review_fixes(true, vec![i], resolution);
+                                }
+                            }
+                            // NOTE: ぶつかるもののカードには、チェーンをまとめて補正するボタンはない
+                            GapCardAction::FixChain(_) => {}
+                            GapCardAction::Remove => {
+                                remove_keys.insert(key);
+                            }
+                        }
+                    }
+                    self.remove_gaps(true, &remove_keys);
+                });
+        });
+    }
+
+    fn render_fix_failures(&mut self, ui: &mut egui::Ui) {
+        if self.fix_failures.is_empty() {
+            return;
+        }
+        let count = self.fix_failures.len().to_string();
+        egui::CollapsingHeader::new(tr_format(
+            "補正できなかったもの：{count}件",
+            &[("count", &count)],
+        ))
+        .id_salt("fix_failures")
+        .show(ui, |ui| {
+            for (gap, reason) in &self.fix_failures {
+                let frame = gap.frame.to_string();
+                label_truncated(
+                    ui,
+                    tr_format(
+                        "{layer}・{frame}f：{reason}",
+                        &[
+                            ("layer", &gap.layer_name),
+                            ("frame", &frame),
+                            ("reason", reason),
+                        ],
+                    ),
+                );
+            }
+            if full_width_button(ui, &tr("閉じる")).clicked() {
+                self.fix_failures.clear();
+            }
         });
     }
 
@@ -310,7 +1854,7 @@ impl QuantizerGuiApp {
         ui: &mut egui::Ui,
         gap: &crate::find::OffbeatInfo,
         is_selected: bool,
-    ) -> bool {
+    ) -> Option<GapCardAction> {
         let frame = egui::Frame::group(ui.style())
             .fill(ui.visuals().faint_bg_color)
             .stroke(if is_selected {
@@ -320,7 +1864,7 @@ impl QuantizerGuiApp {
             })
             .inner_margin(egui::Margin::symmetric(8, 4));
         let available_width = ui.available_width();
-        let mut remove = false;
+        let mut action = None;
         ui.allocate_ui_with_layout(
             egui::vec2(available_width, 0.0),
             egui::Layout::top_down(egui::Align::Min),
@@ -377,12 +1921,34 @@ impl QuantizerGuiApp {
                         );
                         let frame = gap.frame.to_string();
                         ui.label(tr_format("フレーム：{frame}f", &[("frame", &frame)]));
+                        if let Some(musical_time) = &gap.musical_time {
+                            let bar = musical_time.bar.to_string();
+                            let beat = musical_time.beat.to_string();
+                            let tick = musical_time.tick.to_string();
+                            ui.label(tr_format(
+                                "位置：{bar}小節 {beat}拍 + {tick}tick",
+                                &[("bar", &bar), ("beat", &beat), ("tick", &tick)],
+                            ));
+                        }
                         let offset = if gap.offset_frames > 0 {
                             format!("+{}f", gap.offset_frames)
                         } else {
                             format!("{}f", gap.offset_frames)
                         };
                         ui.label(tr_format("ずれ：{offset}", &[("offset", &offset)]));
+                        if let crate::find::TimingType::EndThenStart { gap_frames, .. } =
+                            &gap.timing_type
+                            && *gap_frames > 0
+                        {
+                            let gap_frames = gap_frames.to_string();
+                            ui.label(tr_format(
+                                "隙間：{gap}f（補正で詰めます）",
+                                &[("gap", &gap_frames)],
+                            ));
+                        }
+                        if let Some(blocked) = &gap.blocked {
+                            warn_label(ui, &blocked_reason(blocked));
+                        }
                         ui.add_space(4.0);
                         if self.gap_action_button(
                             ui,
@@ -395,21 +1961,69 @@ impl QuantizerGuiApp {
                                 tracing::error!("Failed to jump to gap: {e}");
                             }
                         }
-                        if self.gap_action_button(ui, &tr("補正"), egui::Key::A, is_selected) {
-                            let res = crate::find::fix_offbeat(gap);
-                            remove = true;
-                            match res {
-                                Ok(_) => {
-                                    tracing::info!("Gap fixed successfully");
+                        match &gap.blocked {
+                            // NOTE: 向きの制約が両立しないものは、向きを変えないと補正できない
+                            Some(crate::find::Blocked::DirectionConflict) => {}
+                            Some(_) => {
+                                let length_mode = self
+                                    .fix_context
+                                    .as_ref()
+                                    .is_some_and(|context| context.find_target.length_note.is_some());
+                                let own_neighbour = matches!(
+                                    &gap.blocked,
+                                    Some(crate::find::Blocked::Neighbour { neighbour, .. })
+                                        if neighbour.object == gap.object
+                                );
+                                for (resolution, label) in [
+                                    (
+                                        crate::find::BlockResolution::NextGridLine,
+                                        tr("ぶつからないグリッドに合わせる"),
+                                    ),
+                                    (
+                                        crate::find::BlockResolution::ShortenNeighbour,
+                                        tr("隣を縮める"),
+                                    ),
+                                    (
+                                        crate::find::BlockResolution::PushNeighbour,
+                                        tr("隣を押し出す"),
+                                    ),
+                                ] {
+                                    // NOTE: 長さを見ているときは、グリッドに合わせても長さは揃わず、
+                                    //       同じオブジェクトの区間を縮めると長さや中身が変わる。
+                                    //       同じオブジェクトの区間は押し出せない
+                                    let hidden = match resolution {
+                                        crate::find::BlockResolution::NextGridLine => length_mode,
+                                        crate::find::BlockResolution::ShortenNeighbour => {
+                                            length_mode && own_neighbour
+                                        }
+                                        crate::find::BlockResolution::PushNeighbour => own_neighbour,
+                                    };
+                                    if hidden {
+                                        continue;
+                                    }
+                                    if full_width_button(ui, &label).clicked() {
+                                        action = Some(GapCardAction::Fix(Some(resolution)));
+                                    }
                                 }
-                                Err(e) => {
-                                    tracing::error!("Failed to fix gap: {e}");
+                            }
+                            None => {
+                                if self.gap_action_button(ui, &tr("補正"), egui::Key::A, is_selected) {
+                                    action = Some(GapCardAction::Fix(None));
+                                }
+                                if let Some(chain) = gap.chain
+                                    && full_width_button(ui, &tr("チェーンをまとめて補正"))
+                                        .on_hover_text(tr(
+                                            "同じレイヤーでつながって並んでいるオブジェクトのズレを、まとめて確認してから補正します。",
+                                        ))
+                                        .clicked()
+                                {
+                                    action = Some(GapCardAction::FixChain(chain));
                                 }
                             }
                         }
                         if self.gap_action_button(ui, &tr("除外"), egui::Key::E, is_selected) {
                             let res = crate::find::mark_ignored(&[gap.object]);
-                            remove = true;
+                            action = Some(GapCardAction::Remove);
                             match res {
                                 Ok(_) => {
                                     tracing::info!("Gap ignored successfully");
@@ -428,13 +2042,13 @@ impl QuantizerGuiApp {
                             )
                         {
                             tracing::info!("Skipping gap and jumping to next");
-                            remove = true;
+                            action = Some(GapCardAction::Remove);
                         }
                     });
                 });
             },
         );
-        remove
+        action
     }
 
     fn gap_action_button(
@@ -543,8 +2157,12 @@ impl QuantizerGuiApp {
 impl eframe::App for QuantizerGuiApp {
     fn ui(&mut self, ui: &mut egui::Ui, _frame: &mut eframe::Frame) {
         let ctx = ui.ctx().clone();
+        self.poll_onsets(&ctx);
+        self.poll_offset_fit(&ctx);
         if crate::RESET_GAPS_ON_PROJECT_LOAD.swap(false, Ordering::Relaxed) {
             self.gaps = None;
+            self.fix_history.clear();
+            self.applied_fixes.clear();
         }
         if !crate::EDIT_HANDLE.is_ready() {
             egui::CentralPanel::default().show(ui, |ui| {
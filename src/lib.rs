@@ -1,7 +1,9 @@
 mod find;
-mod grid;
+pub mod grid;
 mod gui;
 mod marker;
+mod onset;
+mod tempo_map;
 use aviutl2::tracing;
 use std::sync::atomic::{AtomicBool, Ordering};
 
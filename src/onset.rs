@@ -0,0 +1,194 @@
+use aviutl2::anyhow::{self, Context};
+
+pub const SUPPORTED_EXTENSIONS: [&str; 2] = ["wav", "flac"];
+
+// NOTE: 約5msごとにエネルギーを取り、その増え方（対数）の山をオンセットとみなす
+const HOPS_PER_SECOND: u32 = 200;
+const THRESHOLD_WINDOW_SECONDS: f64 = 0.1;
+const THRESHOLD_OFFSET: f32 = 0.5;
+const MIN_ONSET_INTERVAL_SECONDS: f64 = 0.05;
+const SILENCE_DB: f32 = -60.0;
+
+pub fn detect_onsets_in_file(path: &std::path::Path) -> anyhow::Result<Vec<f64>> {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let envelope = match extension.as_str() {
+        "wav" => read_wav_envelope(path)?,
+        "flac" => read_flac_envelope(path)?,
+        _ => anyhow::bail!("Unsupported audio file: {}", path.display()),
+    };
+    Ok(pick_onsets(&envelope.energies, envelope.hop_seconds()))
+}
+
+fn read_wav_envelope(path: &std::path::Path) -> anyhow::Result<EnvelopeBuilder> {
+    let mut reader = hound::WavReader::open(path)
+        .with_context(|| format!("Failed to open WAV file: {}", path.display()))?;
+    let spec = reader.spec();
+    let mut envelope = EnvelopeBuilder::new(spec.sample_rate, spec.channels as u32);
+    match spec.sample_format {
+        hound::SampleFormat::Float => {
+            for sample in reader.samples::<f32>() {
+                envelope.push(sample.context("Failed to decode WAV file")?);
+            }
+        }
+        hound::SampleFormat::Int => {
+            let scale = (1u64 << (spec.bits_per_sample.max(1) - 1)) as f32;
+            for sample in reader.samples::<i32>() {
+                envelope.push(sample.context("Failed to decode WAV file")? as f32 / scale);
+            }
+        }
+    }
+    Ok(envelope)
+}
+
+fn read_flac_envelope(path: &std::path::Path) -> anyhow::Result<EnvelopeBuilder> {
+    let mut reader = claxon::FlacReader::open(path)
+        .with_context(|| format!("Failed to open FLAC file: {}", path.display()))?;
+    let info = reader.streaminfo();
+    let scale = (1u64 << (info.bits_per_sample.max(1) - 1)) as f32;
+    let mut envelope = EnvelopeBuilder::new(info.sample_rate, info.channels);
+    for sample in reader.samples() {
+        envelope.push(sample.context("Failed to decode FLAC file")? as f32 / scale);
+    }
+    Ok(envelope)
+}
+
+struct EnvelopeBuilder {
+    sample_rate: u32,
+    channels: u32,
+    hop_size: u32,
+    channel_index: u32,
+    frame_sum: f32,
+    previous_sample: f32,
+    hop_energy: f32,
+    hop_position: u32,
+    energies: Vec<f32>,
+}
+
+impl EnvelopeBuilder {
+    fn new(sample_rate: u32, channels: u32) -> Self {
+        Self {
+            sample_rate,
+            channels: channels.max(1),
+            hop_size: (sample_rate / HOPS_PER_SECOND).max(1),
+            channel_index: 0,
+            frame_sum: 0.0,
+            previous_sample: 0.0,
+            hop_energy: 0.0,
+            hop_position: 0,
+            energies: Vec::new(),
+        }
+    }
+
+    fn hop_seconds(&self) -> f64 {
+        self.hop_size as f64 / self.sample_rate.max(1) as f64
+    }
+
+    /// インターリーブされたサンプルを1つ追加する
+    fn push(&mut self, sample: f32) {
+        self.frame_sum += sample;
+        self.channel_index += 1;
+        if self.channel_index < self.channels {
+            return;
+        }
+        let mono = self.frame_sum / self.channels as f32;
+        self.channel_index = 0;
+        self.frame_sum = 0.0;
+
+        // NOTE: 差分を取って低音の持続音よりアタックを強調する
+        let emphasized = mono - self.previous_sample;
+        self.previous_sample = mono;
+        self.hop_energy += emphasized * emphasized;
+        self.hop_position += 1;
+        if self.hop_position == self.hop_size {
+            self.energies.push(self.hop_energy / self.hop_size as f32);
+            self.hop_energy = 0.0;
+            self.hop_position = 0;
+        }
+    }
+}
+
+fn pick_onsets(energies: &[f32], hop_seconds: f64) -> Vec<f64> {
+    let Some(max_energy) = energies.iter().copied().max_by(f32::total_cmp) else {
+        return Vec::new();
+    };
+    if max_energy <= 0.0 {
+        return Vec::new();
+    }
+    let silence = max_energy * 10f32.powf(SILENCE_DB / 10.0);
+    let log_energies: Vec<f32> = energies
+        .iter()
+        .map(|energy| energy.max(silence).ln())
+        .collect();
+    let novelty: Vec<f32> = std::iter::once(0.0)
+        .chain(
+            log_energies
+                .windows(2)
+                .map(|window| (window[1] - window[0]).max(0.0)),
+        )
+        .collect();
+
+    let window = ((THRESHOLD_WINDOW_SECONDS / hop_seconds).round() as usize).max(1);
+    let min_interval = MIN_ONSET_INTERVAL_SECONDS / hop_seconds;
+    let mut onsets: Vec<f64> = Vec::new();
+    let mut last_onset_index: Option<usize> = None;
+    for (i, &value) in novelty.iter().enumerate() {
+        let range = i.saturating_sub(window)..(i + window + 1).min(novelty.len());
+        let local = &novelty[range.clone()];
+        let mean = local.iter().sum::<f32>() / local.len() as f32;
+        if value < mean + THRESHOLD_OFFSET {
+            continue;
+        }
+        if local.iter().any(|&other| other > value) {
+            continue;
+        }
+        if let Some(last_onset_index) = last_onset_index
+            && ((i - last_onset_index) as f64) < min_interval
+        {
+            continue;
+        }
+        last_onset_index = Some(i);
+        onsets.push(i as f64 * hop_seconds);
+    }
+    onsets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pick_onsets_finds_bursts() {
+        let sample_rate = 48000;
+        let bursts = [0.5, 1.0, 1.75];
+        let mut envelope = EnvelopeBuilder::new(sample_rate, 2);
+        for i in 0..(sample_rate * 2) {
+            let t = i as f64 / sample_rate as f64;
+            let sample = bursts
+                .iter()
+                .filter(|&&burst| t >= burst)
+                .map(|&burst| {
+                    let elapsed = t - burst;
+                    (elapsed * 1000.0 * std::f64::consts::TAU).sin() * (-elapsed * 30.0).exp()
+                })
+                .sum::<f64>() as f32
+                * 0.5;
+            envelope.push(sample);
+            envelope.push(sample);
+        }
+
+        let onsets = pick_onsets(&envelope.energies, envelope.hop_seconds());
+
+        assert_eq!(onsets.len(), bursts.len(), "{onsets:?}");
+        for (onset, burst) in onsets.iter().zip(bursts) {
+            assert!((onset - burst).abs() < 0.01, "{onset} != {burst}");
+        }
+    }
+
+    #[test]
+    fn pick_onsets_ignores_silence() {
+        assert!(pick_onsets(&[0.0; 100], 0.005).is_empty());
+    }
+}
@@ -0,0 +1,495 @@
+use aviutl2::anyhow::{self, Context};
+
+const DEFAULT_MICROSECONDS_PER_QUARTER: u32 = 500_000;
+
+#[derive(Debug, Clone, Copy)]
+enum MidiTempoEvent {
+    Tempo { microseconds_per_quarter: u32 },
+    TimeSignature { numerator: u8, denominator: u32 },
+}
+
+pub const SUPPORTED_EXTENSIONS: [&str; 5] = ["mid", "midi", "csv", "tsv", "txt"];
+
+pub fn read_tempo_map_file(
+    path: &std::path::Path,
+) -> anyhow::Result<Vec<aviutl2::generic::BpmInfo>> {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "mid" | "midi" => read_midi_file(path),
+        _ => read_csv_file(path),
+    }
+}
+
+pub fn read_midi_file(path: &std::path::Path) -> anyhow::Result<Vec<aviutl2::generic::BpmInfo>> {
+    let bytes = std::fs::read(path)
+        .with_context(|| format!("Failed to read MIDI file: {}", path.display()))?;
+    parse_midi(&bytes)
+}
+
+pub fn read_csv_file(path: &std::path::Path) -> anyhow::Result<Vec<aviutl2::generic::BpmInfo>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read tempo map file: {}", path.display()))?;
+    parse_csv(&text)
+}
+
+#[derive(Debug, Clone, Copy)]
+enum CsvColumn {
+    Start,
+    Tempo,
+    Beat,
+    Offset,
+}
+
+impl CsvColumn {
+    fn from_header(header: &str) -> Option<Self> {
+        match header.trim().to_lowercase().as_str() {
+            "start" | "time" | "seconds" | "sec" | "position" => Some(Self::Start),
+            "tempo" | "bpm" => Some(Self::Tempo),
+            "beat" | "beats" | "numerator" => Some(Self::Beat),
+            "offset" => Some(Self::Offset),
+            _ => None,
+        }
+    }
+}
+
+// NOTE: 列は「開始位置（秒）, BPM, 拍子, オフセット（秒）」の順。
+// ヘッダー行がある場合は列名（start/time, tempo/bpm, beat, offset）で並び替えられる。
+fn parse_csv(text: &str) -> anyhow::Result<Vec<aviutl2::generic::BpmInfo>> {
+    let mut columns = vec![
+        Some(CsvColumn::Start),
+        Some(CsvColumn::Tempo),
+        Some(CsvColumn::Beat),
+        Some(CsvColumn::Offset),
+    ];
+    let mut bpm_list = Vec::new();
+    for (line_index, line) in text.lines().enumerate() {
+        let line = line.trim().trim_start_matches('\u{feff}');
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let separator = [',', '\t', ';']
+            .into_iter()
+            .find(|separator| line.contains(*separator))
+            .unwrap_or(',');
+        let cells: Vec<&str> = line.split(separator).map(str::trim).collect();
+
+        if bpm_list.is_empty() && cells.iter().any(|cell| cell.parse::<f64>().is_err()) {
+            let header_columns: Vec<_> = cells
+                .iter()
+                .map(|cell| CsvColumn::from_header(cell))
+                .collect();
+            if header_columns.iter().any(Option::is_some) {
+                columns = header_columns;
+                continue;
+            }
+        }
+
+        let mut bpm = aviutl2::generic::BpmInfo {
+            tempo: 0.0,
+            beat: 4,
+            start: 0.0,
+            offset: 0.0,
+        };
+        let mut has_tempo = false;
+        for (cell, column) in cells.iter().zip(&columns) {
+            let Some(column) = column else {
+                continue;
+            };
+            if cell.is_empty() {
+                continue;
+            }
+            let value: f64 = cell
+                .parse()
+                .with_context(|| format!("Invalid number at line {}: {cell}", line_index + 1))?;
+            match column {
+                CsvColumn::Start => bpm.start = value,
+                CsvColumn::Tempo => {
+                    bpm.tempo = value as f32;
+                    has_tempo = true;
+                }
+                CsvColumn::Beat => bpm.beat = value as i32,
+                CsvColumn::Offset => bpm.offset = value as f32,
+            }
+        }
+        if !has_tempo {
+            anyhow::bail!("Tempo is missing at line {}", line_index + 1);
+        }
+        if bpm.tempo <= 0.0 || bpm.beat <= 0 {
+            anyhow::bail!("Invalid tempo or beat at line {}", line_index + 1);
+        }
+        bpm_list.push(bpm);
+    }
+    if bpm_list.is_empty() {
+        anyhow::bail!("Tempo map is empty");
+    }
+    bpm_list.sort_by(|left, right| left.start.total_cmp(&right.start));
+    Ok(bpm_list)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, position: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.position >= self.bytes.len()
+    }
+
+    fn read_bytes(&mut self, len: usize) -> anyhow::Result<&'a [u8]> {
+        let bytes = self
+            .bytes
+            .get(self.position..self.position + len)
+            .context("Unexpected end of MIDI data")?;
+        self.position += len;
+        Ok(bytes)
+    }
+
+    fn read_u8(&mut self) -> anyhow::Result<u8> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn peek_u8(&self) -> anyhow::Result<u8> {
+        self.bytes
+            .get(self.position)
+            .copied()
+            .context("Unexpected end of MIDI data")
+    }
+
+    fn read_u16(&mut self) -> anyhow::Result<u16> {
+        let bytes = self.read_bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn read_u32(&mut self) -> anyhow::Result<u32> {
+        let bytes = self.read_bytes(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn read_variable_length(&mut self) -> anyhow::Result<u32> {
+        let mut value = 0u32;
+        for _ in 0..4 {
+            let byte = self.read_u8()?;
+            value = (value << 7) | (byte & 0x7f) as u32;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        anyhow::bail!("Variable length quantity is too long")
+    }
+}
+
+fn read_track_events(track: &[u8], events: &mut Vec<(u64, MidiTempoEvent)>) -> anyhow::Result<()> {
+    let mut reader = Reader::new(track);
+    let mut tick = 0u64;
+    let mut running_status = None;
+    while !reader.is_empty() {
+        tick += reader.read_variable_length()? as u64;
+        let status = if reader.peek_u8()? & 0x80 != 0 {
+            reader.read_u8()?
+        } else {
+            running_status.context("Running status without previous status")?
+        };
+        match status {
+            0xff => {
+                running_status = None;
+                let meta_type = reader.read_u8()?;
+                let len = reader.read_variable_length()? as usize;
+                let data = reader.read_bytes(len)?;
+                match meta_type {
+                    0x51 if len == 3 => events.push((
+                        tick,
+                        MidiTempoEvent::Tempo {
+                            microseconds_per_quarter: u32::from_be_bytes([
+                                0, data[0], data[1], data[2],
+                            ]),
+                        },
+                    )),
+                    0x58 if len >= 2 => events.push((
+                        tick,
+                        MidiTempoEvent::TimeSignature {
+                            numerator: data[0],
+                            denominator: 1u32
+                                .checked_shl(data[1] as u32)
+                                .context("Invalid time signature denominator")?,
+                        },
+                    )),
+                    0x2f => break,
+                    _ => {}
+                }
+            }
+            0xf0 | 0xf7 => {
+                running_status = None;
+                let len = reader.read_variable_length()? as usize;
+                reader.read_bytes(len)?;
+            }
+            0x80..=0xef => {
+                running_status = Some(status);
+                let data_len = if (0xc0..=0xdf).contains(&status) {
+                    1
+                } else {
+                    2
+                };
+                reader.read_bytes(data_len)?;
+            }
+            _ => anyhow::bail!("Unsupported MIDI status byte: {status:#04x}"),
+        }
+    }
+    Ok(())
+}
+
+fn parse_midi(bytes: &[u8]) -> anyhow::Result<Vec<aviutl2::generic::BpmInfo>> {
+    let mut reader = Reader::new(bytes);
+    if reader.read_bytes(4)? != b"MThd" {
+        anyhow::bail!("Not a Standard MIDI File");
+    }
+    let header_len = reader.read_u32()? as usize;
+    let header = reader.read_bytes(header_len)?;
+    let mut header = Reader::new(header);
+    let _format = header.read_u16()?;
+    let track_count = header.read_u16()?;
+    let division = header.read_u16()?;
+    if division & 0x8000 != 0 {
+        anyhow::bail!("SMPTE time division is not supported");
+    }
+    let ticks_per_quarter = division as u64;
+    if ticks_per_quarter == 0 {
+        anyhow::bail!("Invalid time division: 0");
+    }
+
+    let mut events = Vec::new();
+    // NOTE: 知らないチャンクはトラック数に数えずに読み飛ばす
+    let mut read_tracks = 0;
+    while read_tracks < track_count && !reader.is_empty() {
+        let chunk_type = reader.read_bytes(4)?;
+        let chunk_len = reader.read_u32()? as usize;
+        let chunk = reader.read_bytes(chunk_len)?;
+        if chunk_type == b"MTrk" {
+            read_track_events(chunk, &mut events)?;
+            read_tracks += 1;
+        }
+    }
+    // NOTE: 同じtickでは拍子を先に適用する（小節の頭の判定に使うため）
+    events
+        .sort_by_key(|(tick, event)| (*tick, matches!(event, MidiTempoEvent::Tempo { .. }) as u8));
+
+    Ok(build_bpm_list(&events, ticks_per_quarter))
+}
+
+fn build_bpm_list(
+    events: &[(u64, MidiTempoEvent)],
+    ticks_per_quarter: u64,
+) -> Vec<aviutl2::generic::BpmInfo> {
+    let mut microseconds_per_quarter = DEFAULT_MICROSECONDS_PER_QUARTER;
+    let mut numerator = 4u8;
+    let mut denominator = 4u32;
+    let mut bar_origin_tick = 0u64;
+    let mut current_tick = 0u64;
+    let mut current_seconds = 0.0f64;
+
+    let mut bpm_list: Vec<aviutl2::generic::BpmInfo> = Vec::new();
+    let mut index = 0;
+    loop {
+        let tick = if bpm_list.is_empty() {
+            0
+        } else {
+            match events.get(index) {
+                Some((tick, _)) => *tick,
+                None => break,
+            }
+        };
+        current_seconds += (tick - current_tick) as f64 / ticks_per_quarter as f64
+            * microseconds_per_quarter as f64
+            / 1_000_000.0;
+        current_tick = tick;
+        let mut time_signature_changed = false;
+        while let Some((event_tick, event)) = events.get(index)
+            && *event_tick == tick
+        {
+            match *event {
+                MidiTempoEvent::Tempo {
+                    microseconds_per_quarter: value,
+                } => microseconds_per_quarter = value.max(1),
+                MidiTempoEvent::TimeSignature {
+                    numerator: new_numerator,
+                    denominator: new_denominator,
+                } => {
+                    numerator = new_numerator.max(1);
+                    denominator = new_denominator;
+                    bar_origin_tick = tick;
+                    time_signature_changed = true;
+                }
+            }
+            index += 1;
+        }
+
+        // NOTE: AviUtl2のBPMは拍子の分母の音符を1拍とする
+        let quarter_tempo = 60_000_000.0 / microseconds_per_quarter as f64;
+        let tempo = quarter_tempo * denominator as f64 / 4.0;
+        let ticks_per_bar = ticks_per_quarter * 4 * numerator as u64 / denominator as u64;
+        let ticks_to_next_bar = if ticks_per_bar == 0 {
+            0
+        } else {
+            (ticks_per_bar - (tick - bar_origin_tick) % ticks_per_bar) % ticks_per_bar
+        };
+        let offset = ticks_to_next_bar as f64 / ticks_per_quarter as f64 * 60.0 / quarter_tempo;
+        let bpm = aviutl2::generic::BpmInfo {
+            tempo: tempo as f32,
+            beat: numerator as i32,
+            start: current_seconds,
+            offset: offset as f32,
+        };
+        if let Some(last) = bpm_list.last()
+            && last.tempo == bpm.tempo
+            && last.beat == bpm.beat
+            && !time_signature_changed
+        {
+            continue;
+        }
+        bpm_list.push(bpm);
+    }
+    bpm_list
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(events: &[u8]) -> Vec<u8> {
+        let mut track = b"MTrk".to_vec();
+        track.extend_from_slice(&(events.len() as u32).to_be_bytes());
+        track.extend_from_slice(events);
+        track
+    }
+
+    fn midi(ticks_per_quarter: u16, tracks: &[Vec<u8>]) -> Vec<u8> {
+        let mut midi = b"MThd".to_vec();
+        midi.extend_from_slice(&6u32.to_be_bytes());
+        midi.extend_from_slice(&1u16.to_be_bytes());
+        midi.extend_from_slice(&(tracks.len() as u16).to_be_bytes());
+        midi.extend_from_slice(&ticks_per_quarter.to_be_bytes());
+        for track in tracks {
+            midi.extend_from_slice(track);
+        }
+        midi
+    }
+
+    #[test]
+    fn parse_midi_reads_tempo_and_time_signature() {
+        let conductor = track(&[
+            // 0: 3/4, 120 BPM
+            0x00, 0xff, 0x58, 0x04, 0x03, 0x02, 0x18, 0x08, //
+            0x00, 0xff, 0x51, 0x03, 0x07, 0xa1, 0x20, //
+            // 2880 (= 2 bars): 60 BPM
+            0x96, 0x40, 0xff, 0x51, 0x03, 0x0f, 0x42, 0x40, //
+            0x00, 0xff, 0x2f, 0x00,
+        ]);
+        let notes = track(&[
+            0x00, 0x90, 0x3c, 0x64, //
+            0x83, 0x60, 0x3c, 0x00, // running status
+            0x00, 0xff, 0x2f, 0x00,
+        ]);
+
+        let bpm_list = parse_midi(&midi(480, &[conductor, notes])).unwrap();
+
+        assert_eq!(bpm_list.len(), 2);
+        assert_eq!(bpm_list[0].tempo, 120.0);
+        assert_eq!(bpm_list[0].beat, 3);
+        assert_eq!(bpm_list[0].start, 0.0);
+        assert_eq!(bpm_list[0].offset, 0.0);
+        assert_eq!(bpm_list[1].tempo, 60.0);
+        assert_eq!(bpm_list[1].beat, 3);
+        assert!((bpm_list[1].start - 3.0).abs() < 1e-9);
+        assert_eq!(bpm_list[1].offset, 0.0);
+    }
+
+    #[test]
+    fn parse_midi_sets_offset_for_tempo_change_inside_bar() {
+        let conductor = track(&[
+            // 480 (= 2nd beat of 4/4): 60 BPM
+            0x83, 0x60, 0xff, 0x51, 0x03, 0x0f, 0x42, 0x40, //
+            0x00, 0xff, 0x2f, 0x00,
+        ]);
+
+        let bpm_list = parse_midi(&midi(480, &[conductor])).unwrap();
+
+        assert_eq!(bpm_list.len(), 2);
+        assert_eq!(bpm_list[0].tempo, 120.0);
+        assert!((bpm_list[1].start - 0.5).abs() < 1e-9);
+        assert_eq!(bpm_list[1].offset, 3.0);
+    }
+
+    #[test]
+    fn parse_midi_uses_denominator_as_beat() {
+        let conductor = track(&[
+            // 6/8, quarter = 100 BPM
+            0x00, 0xff, 0x58, 0x04, 0x06, 0x03, 0x18, 0x08, //
+            0x00, 0xff, 0x51, 0x03, 0x09, 0x27, 0xc0, //
+            0x00, 0xff, 0x2f, 0x00,
+        ]);
+
+        let bpm_list = parse_midi(&midi(480, &[conductor])).unwrap();
+
+        assert_eq!(bpm_list.len(), 1);
+        assert_eq!(bpm_list[0].tempo, 200.0);
+        assert_eq!(bpm_list[0].beat, 6);
+    }
+
+    #[test]
+    fn parse_midi_skips_unknown_chunks() {
+        let conductor = track(&[
+            // 0: 60 BPM
+            0x00, 0xff, 0x51, 0x03, 0x0f, 0x42, 0x40, //
+            0x00, 0xff, 0x2f, 0x00,
+        ]);
+        let mut bytes = midi(480, std::slice::from_ref(&conductor));
+        // NOTE: ヘッダーの直後に知らないチャンクを挟む
+        let header_len = bytes.len() - conductor.len();
+        bytes.splice(
+            header_len..header_len,
+            [b"XFIH".as_slice(), &3u32.to_be_bytes(), &[1, 2, 3]].concat(),
+        );
+
+        let bpm_list = parse_midi(&bytes).unwrap();
+
+        assert_eq!(bpm_list.len(), 1);
+        assert_eq!(bpm_list[0].tempo, 60.0);
+    }
+
+    #[test]
+    fn parse_csv_reads_positional_columns() {
+        let bpm_list = parse_csv("0,120,4\n12.5,90,3,0.25\n").unwrap();
+
+        assert_eq!(bpm_list.len(), 2);
+        assert_eq!(bpm_list[0].tempo, 120.0);
+        assert_eq!(bpm_list[0].beat, 4);
+        assert_eq!(bpm_list[0].start, 0.0);
+        assert_eq!(bpm_list[1].tempo, 90.0);
+        assert_eq!(bpm_list[1].beat, 3);
+        assert_eq!(bpm_list[1].start, 12.5);
+        assert_eq!(bpm_list[1].offset, 0.25);
+    }
+
+    #[test]
+    fn parse_csv_reads_columns_by_header() {
+        let bpm_list = parse_csv("BPM\tTime\tMarker\n140\t0\tIntro\n70\t30\tBridge\n").unwrap();
+
+        assert_eq!(bpm_list.len(), 2);
+        assert_eq!(bpm_list[0].tempo, 140.0);
+        assert_eq!(bpm_list[0].beat, 4);
+        assert_eq!(bpm_list[1].tempo, 70.0);
+        assert_eq!(bpm_list[1].start, 30.0);
+    }
+
+    #[test]
+    fn parse_csv_rejects_missing_tempo() {
+        assert!(parse_csv("start,offset\n0,0\n").is_err());
+    }
+}